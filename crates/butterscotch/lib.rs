@@ -6,7 +6,7 @@ pub use butterscotch_common::*;
 pub use butterscotch_core::*;
 
 pub mod ecs {
-    pub use butterscotch_ecs_derive::Component;
+    pub use butterscotch_ecs_derive::{Bundle, Component, Reflect};
     pub use butterscotch_ecs::*;
 }
 
@@ -32,7 +32,11 @@ impl GIDRegistry {
                 // Check gen to prevent id aliasing
                 if gid.get_gen() != *generation { return false }
                 *generation = 0;
-                self.freelist.push_back(gid);
+
+                // Bump the generation so stale copies of the gid are rejected, retire the slot once exhausted
+                if let Some(renewed) = gid.try_renew_as(gidx) {
+                    self.freelist.push_back(renewed);
+                }
                 true
             },
             None => false
@@ -41,7 +45,7 @@ impl GIDRegistry {
 
     pub fn contains_key(&self, gid: GID) -> bool {
         match self.gen_lookup.get(gid.get_idx()) {
-            Some(generation) => gid.get_gen() != *generation,
+            Some(generation) => gid.is_valid() && gid.get_gen() == *generation,
             None             => false,
         }
     }
@@ -2,7 +2,7 @@
 ** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
 ** ************************************************************************ */
 
-use butterscotch_chunky_vec::{ChunkSize, ChunkyVecIter};
+use butterscotch_chunky_vec::{ChunkSize, ChunkyVecIter, ChunkyVecIterMut};
 
 use super::gid::GID;
 use crate::{container::ChunkyVec, utility::GenericRetype};
@@ -26,15 +26,27 @@ impl<T> GIDStore<T> {
 
     pub fn insert(&mut self, gid: GID, v: T) {
         let idx = gid.get_idx();
-        if self.data.len() <= idx { self.expand_lookup(); }
+        while self.lookup.len() <= idx { self.expand_lookup(); }
         if self.lookup[idx].is_valid() { panic!("Slot already contains value"); }
         self.set_raw(gid, v);
     }
 
-    pub fn replace(&mut self, gid: GID, v: T) {
+    pub fn replace(&mut self, gid: GID, v: T) -> Option<T> {
+        if let Some(data) = self.get_mut(gid) {
+            return Some(std::mem::replace(data, v));
+        }
+
         let idx = gid.get_idx();
-        if self.data.len() <= idx { self.expand_lookup(); }
+        while self.lookup.len() <= idx { self.expand_lookup(); }
+
+        // Evict a value left behind by another generation of the key
+        if self.lookup[idx].is_valid() {
+            let stale = self.lookup[idx].with_idx(idx);
+            self.remove(stale);
+        }
+
         self.set_raw(gid, v);
+        None
     }
 
     pub fn remove(&mut self, gid: GID) -> Option<T> {
@@ -112,10 +124,9 @@ impl<T> GIDStore<T> {
         self.data.iter()
     }
 
-    // TODO fix....
-    //pub fn iter_mut(&mut self) -> core::slice::IterMut<'_, T> {
-    //    self.data.iter_mut() 
-    //}
+    pub fn iter_mut(&mut self) -> ChunkyVecIterMut<'_, T> {
+        self.data.iter_mut()
+    }
 
     pub fn reserve(&mut self, additional: usize) {
         self.data.reserve(additional);
@@ -2,7 +2,7 @@
 ** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
 ** ************************************************************************ */
 
-use butterscotch_chunky_vec::{ChunkSize, ChunkyVecIter};
+use butterscotch_chunky_vec::{ChunkSize, ChunkyVecIter, ChunkyVecIterMut};
 
 use super::{ComponentMapKeyIter, GIDRegistry, GIDStore, GID};
 
@@ -69,10 +69,9 @@ impl<T> SlotMap<T> {
         self.store.iter()
     }
 
-    // TODO fix....
-    //pub fn iter_mut(&mut self) -> core::slice::IterMut<'_, T> {
-    //    self.store.iter_mut()
-    //}
+    pub fn iter_mut(&mut self) -> ChunkyVecIterMut<'_, T> {
+        self.store.iter_mut()
+    }
 
     pub fn reserve(&mut self, additional: usize) {
         self.registry.reserve(additional);
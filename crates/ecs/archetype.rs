@@ -2,6 +2,218 @@
 ** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
 ** ************************************************************************ */
 
-struct Archetype {
-    
-}
\ No newline at end of file
+use std::collections::HashMap;
+
+use butterscotch_common::container::{ChunkSize, GIDStore};
+
+use crate::{BadIntHasher, ComponentID, EntityID};
+
+#[repr(transparent)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct ArchetypeID(pub u32);
+
+impl ArchetypeID {
+    /// The archetype of entities without any components
+    pub const EMPTY: ArchetypeID = ArchetypeID(0);
+
+    #[inline(always)]
+    pub fn get_idx(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Where an entity's components live, the row is shared by every column of the archetype
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EntityLocation {
+    pub archetype: ArchetypeID,
+    pub row: usize,
+}
+
+/// Cached transitions to the archetypes that differ by a single component
+#[derive(Debug, Default, Clone, Copy)]
+struct ArchetypeEdge {
+    add:    Option<ArchetypeID>,
+    remove: Option<ArchetypeID>,
+}
+
+#[derive(Debug)]
+pub struct Archetype {
+    id: ArchetypeID,
+    components: Vec<ComponentID>,
+    entities: Vec<EntityID>,
+    edges: HashMap<ComponentID, ArchetypeEdge, BadIntHasher>,
+}
+
+impl Archetype {
+
+    fn new(id: ArchetypeID, components: Vec<ComponentID>) -> Self {
+        Self{
+            id,
+            components,
+            entities: Default::default(),
+            edges: Default::default(),
+        }
+    }
+
+    pub fn id(&self) -> ArchetypeID {
+        self.id
+    }
+
+    /// Sorted component set shared by every entity in the archetype
+    pub fn components(&self) -> &[ComponentID] {
+        &self.components
+    }
+
+    /// Entities in row order
+    pub fn entities(&self) -> &[EntityID] {
+        &self.entities
+    }
+
+    pub fn contains(&self, id: ComponentID) -> bool {
+        self.components.binary_search(&id).is_ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    fn push(&mut self, eid: EntityID) -> usize {
+        self.entities.push(eid);
+        return self.entities.len() - 1;
+    }
+
+    /// Removes the row by swapping in the last entity, returning the entity that now occupies the row
+    fn swap_remove(&mut self, row: usize) -> Option<EntityID> {
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+}
+
+/// Owns every archetype, the graph between them and the location of every entity.
+///
+/// Component data isn't stored here, each ComponentStore keeps one column per archetype
+/// and is expected to mirror the row operations performed on the archetype.
+#[derive(Debug)]
+pub struct Archetypes {
+    archetypes: Vec<Archetype>,
+    lookup: HashMap<Vec<ComponentID>, ArchetypeID>,
+    locations: GIDStore<EntityLocation>,
+}
+
+impl Default for Archetypes {
+    fn default() -> Self {
+        Self::new(ChunkSize::Elements(4096))
+    }
+}
+
+impl Archetypes {
+
+    pub fn new(chunk_size: ChunkSize) -> Self {
+        let mut result = Self{
+            archetypes: Default::default(),
+            lookup: Default::default(),
+            locations: GIDStore::new(chunk_size),
+        };
+        result.get_or_insert(Vec::new());
+        return result;
+    }
+
+    pub fn get(&self, id: ArchetypeID) -> &Archetype {
+        &self.archetypes[id.get_idx()]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Archetype> {
+        self.archetypes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.archetypes.len()
+    }
+
+    pub fn location(&self, eid: EntityID) -> Option<EntityLocation> {
+        self.locations.get(eid).copied()
+    }
+
+    /// Finds or creates the archetype for a component set, the set must be sorted
+    pub fn get_or_insert(&mut self, components: Vec<ComponentID>) -> ArchetypeID {
+        debug_assert!(components.windows(2).all(|v| v[0] < v[1]), "Archetype components must be sorted and unique");
+        if let Some(id) = self.lookup.get(&components) { return *id; }
+
+        let id = ArchetypeID(self.archetypes.len() as u32);
+        self.lookup.insert(components.clone(), id);
+        self.archetypes.push(Archetype::new(id, components));
+        return id;
+    }
+
+    /// The archetype reached by adding a component, following the cached edge where possible
+    pub fn with_component(&mut self, source: ArchetypeID, id: ComponentID) -> ArchetypeID {
+        if let Some(target) = self.get(source).edges.get(&id).and_then(|v| v.add) { return target; }
+
+        let mut components = self.get(source).components.clone();
+        match components.binary_search(&id) {
+            Ok(_)  => return source,
+            Err(i) => components.insert(i, id),
+        }
+
+        let target = self.get_or_insert(components);
+        self.link(source, target, id);
+        return target;
+    }
+
+    /// The archetype reached by removing a component, following the cached edge where possible
+    pub fn without_component(&mut self, source: ArchetypeID, id: ComponentID) -> ArchetypeID {
+        if let Some(target) = self.get(source).edges.get(&id).and_then(|v| v.remove) { return target; }
+
+        let mut components = self.get(source).components.clone();
+        match components.binary_search(&id) {
+            Ok(i)  => { components.remove(i); },
+            Err(_) => return source,
+        }
+
+        let target = self.get_or_insert(components);
+        self.link(target, source, id);
+        return target;
+    }
+
+    /// Places a new entity at the end of an archetype
+    pub fn insert(&mut self, eid: EntityID, archetype: ArchetypeID) -> EntityLocation {
+        let row = self.archetypes[archetype.get_idx()].push(eid);
+        let location = EntityLocation{ archetype, row };
+        self.locations.insert(eid, location);
+        return location;
+    }
+
+    /// Removes an entity, returning where it used to be
+    pub fn remove(&mut self, eid: EntityID) -> Option<EntityLocation> {
+        let location = self.locations.remove(eid)?;
+        self.detach_row(location);
+        Some(location)
+    }
+
+    /// Moves an entity to the end of another archetype, returning the old and new locations
+    pub fn relocate(&mut self, eid: EntityID, target: ArchetypeID) -> Option<(EntityLocation, EntityLocation)> {
+        let source = self.location(eid)?;
+        self.detach_row(source);
+
+        let row = self.archetypes[target.get_idx()].push(eid);
+        let location = EntityLocation{ archetype: target, row };
+        *self.locations.get_mut(eid).unwrap() = location;
+        Some((source, location))
+    }
+
+    fn detach_row(&mut self, location: EntityLocation) {
+        // Update the entity swapped into the vacated row
+        if let Some(moved) = self.archetypes[location.archetype.get_idx()].swap_remove(location.row) {
+            *self.locations.get_mut(moved).unwrap() = location;
+        }
+    }
+
+    fn link(&mut self, without: ArchetypeID, with: ArchetypeID, id: ComponentID) {
+        self.archetypes[without.get_idx()].edges.entry(id).or_default().add    = Some(with);
+        self.archetypes[with.get_idx()   ].edges.entry(id).or_default().remove = Some(without);
+    }
+}
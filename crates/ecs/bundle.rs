@@ -0,0 +1,129 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::fmt;
+
+use butterscotch_codegen::generate_tuple_impls;
+
+use crate::{Component, ComponentID, ComponentIDs, ECS, EntityID};
+
+/// Components attached together with a single archetype move, ie. `ecs.spawn_bundle((Position(0, 0), Velocity(1, 0)))`.
+/// Implemented for components and tuples of bundles, and derived for structs whose fields are all bundles.
+pub trait Bundle: Send + Sync + 'static {
+    /// Appends the ID of every component, in the order they're written
+    fn ids(out: &mut ComponentIDs);
+
+    /// Hands every component to the writer
+    fn write(self, writer: &mut BundleWriter);
+}
+
+/// Places the components of a bundle onto an entity that's already been moved to its final archetype
+pub struct BundleWriter<'a> {
+    pub(crate) ecs: &'a mut ECS,
+    pub(crate) eid: EntityID,
+    pub(crate) existing: &'a [ComponentID],
+}
+
+impl<'a> BundleWriter<'a> {
+    pub fn write<T: Component>(&mut self, value: T) {
+        let replace = self.existing.contains(&T::ID);
+        self.ecs.write_bundled(self.eid, value, replace);
+    }
+}
+
+impl<T: Component> Bundle for T {
+    fn ids(out: &mut ComponentIDs) {
+        out.push(T::ID);
+    }
+
+    fn write(self, writer: &mut BundleWriter) {
+        writer.write(self);
+    }
+}
+
+impl Bundle for () {
+    fn ids(_out: &mut ComponentIDs) {}
+    fn write(self, _writer: &mut BundleWriter) {}
+}
+
+generate_tuple_impls!(16, r"
+    impl<%{%TR: Bundle,%}> Bundle for (%{%TR, %}) {
+        fn ids(out: &mut ComponentIDs) {%{
+            %TR::ids(out);%}
+        }
+
+        fn write(self, writer: &mut BundleWriter) {%{
+            %TR::write(%VR, writer);%}
+        }
+    }
+");
+
+// // Prefabs // //
+
+trait PrefabBundle: Send + Sync {
+    fn ids(&self, out: &mut ComponentIDs);
+    fn insert(&self, ecs: &mut ECS, eid: EntityID);
+}
+
+impl<B: Bundle + Clone> PrefabBundle for B {
+    fn ids(&self, out: &mut ComponentIDs) {
+        B::ids(out);
+    }
+
+    fn insert(&self, ecs: &mut ECS, eid: EntityID) {
+        ecs.insert_bundle(eid, self.clone());
+    }
+}
+
+/// A template instantiated with ECS::instantiate, every instance gets its own copy of the components.
+/// Bundles are attached in the order they were added, so later ones override earlier ones.
+#[derive(Default)]
+pub struct Prefab {
+    bundles: Vec<Box<dyn PrefabBundle>>,
+    children: Vec<Prefab>,
+}
+
+impl fmt::Debug for Prefab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ids = ComponentIDs::new();
+        for bundle in &self.bundles { bundle.ids(&mut ids); }
+        f.debug_struct("Prefab").field("components", &ids).field("children", &self.children).finish()
+    }
+}
+
+impl Prefab {
+
+    pub fn new<B: Bundle + Clone>(bundle: B) -> Self {
+        Self::default().with(bundle)
+    }
+
+    /// Adds more components, overriding any the prefab already has
+    pub fn with<B: Bundle + Clone>(mut self, bundle: B) -> Self {
+        self.bundles.push(box bundle);
+        return self;
+    }
+
+    /// Adds a prefab that's instantiated as a child of every instance
+    pub fn with_child(mut self, child: Prefab) -> Self {
+        self.children.push(child);
+        return self;
+    }
+
+    pub fn children(&self) -> &[Prefab] {
+        &self.children
+    }
+
+    /// Spawns the prefab and its children, returning the root
+    pub(crate) fn spawn(&self, ecs: &mut ECS) -> EntityID {
+        let eid = ecs.spawn();
+        for bundle in &self.bundles {
+            bundle.insert(ecs, eid);
+        }
+        for child in &self.children {
+            let child = child.spawn(ecs);
+            ecs.set_parent(child, eid);
+        }
+        return eid;
+    }
+}
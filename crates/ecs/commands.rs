@@ -0,0 +1,186 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::any::{Any, TypeId};
+
+use crate::{Component, ComponentID, DynamicValue, EntityID, Event, EventID, Relation, RelationID, Resource, assert_not_hierarchy};
+
+/// The entity a command applies to, either an existing entity or one spawned earlier in the same buffer
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum CommandTarget {
+    Entity(EntityID),
+    Spawned(SpawnIndex),
+}
+
+/// The order an entity is spawned in by its command buffer, only handed out by Commands::spawn
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct SpawnIndex(pub(crate) u32);
+
+impl SpawnIndex {
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+}
+
+impl From<EntityID> for CommandTarget {
+    fn from(eid: EntityID) -> Self {
+        CommandTarget::Entity(eid)
+    }
+}
+
+pub enum Command {
+    Spawn,
+    Despawn(CommandTarget),
+    Attach(CommandTarget, ComponentID, Box<dyn Any + Send>),
+    Detach(CommandTarget, ComponentID),
+    Set(CommandTarget, ComponentID, Box<dyn Any + Send>),
+    Send(Vec<CommandTarget>, EventID, Box<dyn Any + Send>),
+    InsertResource(Box<dyn Any + Send + Sync>),
+    RemoveResource(TypeId),
+    SetParent(CommandTarget, CommandTarget),
+    RemoveParent(CommandTarget),
+    Relate(CommandTarget, CommandTarget, RelationID, Box<dyn Any + Send>),
+    Unrelate(CommandTarget, CommandTarget, RelationID),
+}
+
+impl std::fmt::Debug for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Spawn                 => f.write_str("Spawn"),
+            Command::Despawn(target)       => f.debug_tuple("Despawn").field(target).finish(),
+            Command::Attach(target, id, _) => f.debug_tuple("Attach").field(target).field(id).finish(),
+            Command::Detach(target, id)    => f.debug_tuple("Detach").field(target).field(id).finish(),
+            Command::Set(target, id, _)    => f.debug_tuple("Set").field(target).field(id).finish(),
+            Command::Send(targets, id, _)  => f.debug_tuple("Send").field(targets).field(id).finish(),
+            Command::InsertResource(_)     => f.write_str("InsertResource"),
+            Command::RemoveResource(id)    => f.debug_tuple("RemoveResource").field(id).finish(),
+            Command::SetParent(child, parent) => f.debug_tuple("SetParent").field(child).field(parent).finish(),
+            Command::RemoveParent(child)   => f.debug_tuple("RemoveParent").field(child).finish(),
+            Command::Relate(source, target, id, _) => f.debug_tuple("Relate").field(source).field(target).field(id).finish(),
+            Command::Unrelate(source, target, id)  => f.debug_tuple("Unrelate").field(source).field(target).field(id).finish(),
+        }
+    }
+}
+
+/// A buffer of deferred mutations, see ECS::apply
+#[derive(Debug, Default)]
+pub struct Commands {
+    commands: Vec<Command>,
+    spawned: u32,
+}
+
+impl Commands {
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Spawns an entity, the returned target can be used by the following commands
+    pub fn spawn(&mut self) -> CommandTarget {
+        self.commands.push(Command::Spawn);
+        self.spawned += 1;
+        return CommandTarget::Spawned(SpawnIndex(self.spawned - 1));
+    }
+
+    pub fn despawn<E: Into<CommandTarget>>(&mut self, target: E) {
+        self.commands.push(Command::Despawn(target.into()));
+    }
+
+    /// Attaches a component, replacing it if the entity already has one
+    pub fn attach<T: Component, E: Into<CommandTarget>>(&mut self, target: E, value: T) {
+        assert_not_hierarchy(T::ID);
+        self.commands.push(Command::Attach(target.into(), T::ID, box value));
+    }
+
+    pub fn detach<T: Component, E: Into<CommandTarget>>(&mut self, target: E) {
+        assert_not_hierarchy(T::ID);
+        self.commands.push(Command::Detach(target.into(), T::ID));
+    }
+
+    /// Attaches a dynamic component, replacing it if the entity already has one
+    pub fn attach_dynamic<E: Into<CommandTarget>>(&mut self, target: E, value: DynamicValue) {
+        self.commands.push(Command::Attach(target.into(), value.id(), box value));
+    }
+
+    pub fn detach_id<E: Into<CommandTarget>>(&mut self, target: E, id: ComponentID) {
+        assert_not_hierarchy(id);
+        self.commands.push(Command::Detach(target.into(), id));
+    }
+
+    /// Overwrites a component, skipped if the entity doesn't have it by the time it's applied
+    pub fn set<T: Component, E: Into<CommandTarget>>(&mut self, target: E, value: T) {
+        assert_not_hierarchy(T::ID);
+        self.commands.push(Command::Set(target.into(), T::ID, box value));
+    }
+
+    /// Sends an event, triggered by the given entities
+    pub fn send<T: Event, E: Into<CommandTarget>, I: IntoIterator<Item = E>>(&mut self, entities: I, data: T) {
+        self.commands.push(Command::Send(entities.into_iter().map(|v| v.into()).collect(), T::ID, box data));
+    }
+
+    /// Inserts a resource, replacing any previous value
+    pub fn insert_resource<R: Resource>(&mut self, value: R) {
+        self.commands.push(Command::InsertResource(box value));
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) {
+        self.commands.push(Command::RemoveResource(TypeId::of::<R>()));
+    }
+
+    /// Moves an entity under another, skipped if either is dead or the parent is the child's own descendant
+    pub fn set_parent<C: Into<CommandTarget>, P: Into<CommandTarget>>(&mut self, child: C, parent: P) {
+        self.commands.push(Command::SetParent(child.into(), parent.into()));
+    }
+
+    pub fn remove_parent<E: Into<CommandTarget>>(&mut self, child: E) {
+        self.commands.push(Command::RemoveParent(child.into()));
+    }
+
+    /// Relates a pair, replacing the value if they're already related. Skipped if either is dead.
+    pub fn relate<R: Relation, S: Into<CommandTarget>, T: Into<CommandTarget>>(&mut self, source: S, target: T, value: R) {
+        self.commands.push(Command::Relate(source.into(), target.into(), R::ID, box value));
+    }
+
+    pub fn unrelate<R: Relation, S: Into<CommandTarget>, T: Into<CommandTarget>>(&mut self, source: S, target: T) {
+        self.commands.push(Command::Unrelate(source.into(), target.into(), R::ID));
+    }
+
+    /// Moves every command from another buffer onto the end of this one
+    pub fn append(&mut self, other: Commands) {
+        let offset = self.spawned;
+        let offset = |target: CommandTarget| match target {
+            CommandTarget::Spawned(v) => CommandTarget::Spawned(SpawnIndex(v.0 + offset)),
+            v                         => v,
+        };
+
+        self.spawned += other.spawned;
+        self.commands.extend(other.commands.into_iter().map(|command| match command {
+            Command::Spawn                     => Command::Spawn,
+            Command::Despawn(target)           => Command::Despawn(offset(target)),
+            Command::Attach(target, id, value) => Command::Attach(offset(target), id, value),
+            Command::Detach(target, id)        => Command::Detach(offset(target), id),
+            Command::Set(target, id, value)    => Command::Set(offset(target), id, value),
+            Command::Send(targets, id, value)  => Command::Send(targets.into_iter().map(offset).collect(), id, value),
+            Command::InsertResource(value)     => Command::InsertResource(value),
+            Command::RemoveResource(id)        => Command::RemoveResource(id),
+            Command::SetParent(child, parent)  => Command::SetParent(offset(child), offset(parent)),
+            Command::RemoveParent(child)       => Command::RemoveParent(offset(child)),
+            Command::Relate(source, target, id, value) => Command::Relate(offset(source), offset(target), id, value),
+            Command::Unrelate(source, target, id)      => Command::Unrelate(offset(source), offset(target), id),
+        }));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// The commands in the order they were recorded
+    pub fn into_inner(self) -> Vec<Command> {
+        self.commands
+    }
+}
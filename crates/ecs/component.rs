@@ -4,9 +4,58 @@
 
 use std::{any::Any, fmt::Debug};
 
+use butterscotch_common::container::ChunkSize;
+
 use crate::ComponentID;
 
-pub trait Component: Any + Debug {
+/// How a component's values are laid out
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ComponentStorage {
+    /// A column per archetype, rows move between columns as components are attached and detached
+    Table,
+}
+
+/// Components are shared between the threads of the system scheduler, so they must be Send + Sync
+pub trait Component: Any + Debug + Send + Sync {
     const ID: ComponentID;
     const ID_STR: &'static str;
-}
\ No newline at end of file
+
+    /// The chunk size used by ECS::register_component_default
+    const CHUNK_SIZE: ChunkSize = ChunkSize::Elements(1024);
+    const STORAGE: ComponentStorage = ComponentStorage::Table;
+}
+
+/// The name of a generic component, built at compile time from its namespaced name and the ID_STRs of its type
+/// arguments, ie. `Test_Wrapper<Test_Position>`
+pub struct ComponentName {
+    bytes: [u8; ComponentName::CAPACITY],
+    len: usize,
+}
+
+impl ComponentName {
+    pub const CAPACITY: usize = 256;
+
+    pub const fn new(parts: &[&str]) -> Self {
+        let mut bytes = [0; Self::CAPACITY];
+        let mut len = 0;
+        let mut i = 0;
+        while i < parts.len() {
+            let part = parts[i].as_bytes();
+            assert!(len + part.len() <= Self::CAPACITY, "Component name is too long");
+            let mut j = 0;
+            while j < part.len() {
+                bytes[len] = part[j];
+                len += 1;
+                j += 1;
+            }
+            i += 1;
+        }
+        return ComponentName{ bytes, len };
+    }
+
+    pub const fn as_str(&'static self) -> &'static str {
+        let (bytes, _) = self.bytes.split_at(self.len);
+        // Only whole strs are copied in
+        return unsafe { std::str::from_utf8_unchecked(bytes) };
+    }
+}
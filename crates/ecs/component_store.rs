@@ -1,74 +1,311 @@
 /* ************************************************************************ **
 ** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
 ** ************************************************************************ */
-use std::any::Any;
-use crate::{Component, ComponentID, EntityID};
-use butterscotch_common::container::{ChunkSize, GIDStore};
+use std::{alloc::Layout, any::Any, cell::UnsafeCell, ops::{Deref, DerefMut}};
+use crate::{ArchetypeID, Archetypes, Component, ComponentID, EntityID, EntityLocation, QueryID, QueryUpdater, Reflect, Tick};
+use butterscotch_common::container::{ChunkSize, ChunkyVec};
 
-pub trait ComponentStoreAny: Any + std::fmt::Debug {
+pub trait ComponentStoreAny: Any + QueryUpdater + std::fmt::Debug {
     fn component_id(&self)     -> ComponentID;
     fn component_id_str(&self) -> &'static str;
+    fn layout(&self)           -> Layout;
+
+    fn as_any(&self)         -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Moves a row into another archetype's column, mirroring Archetypes::relocate
+    fn move_row(&mut self, from: EntityLocation, to: ArchetypeID);
+
+    /// Drops a row, mirroring Archetypes::remove
+    fn remove_row(&mut self, at: EntityLocation);
+
+    /// Removes a row and hands it back boxed, mirroring Archetypes::remove
+    fn take_boxed(&mut self, at: EntityLocation) -> Box<dyn Any>;
+
+    /// Appends a boxed component to an archetype's column, returning the row. Panics if the box doesn't hold the store's type
+    fn insert_boxed(&mut self, archetype: ArchetypeID, value: Box<dyn Any>) -> usize;
+
+    /// Overwrites a row with a boxed component, marking it as changed. Panics if the box doesn't hold the store's type
+    fn replace_boxed(&mut self, at: EntityLocation, value: Box<dyn Any>);
+
+    /// The tick stamped onto components as they're inserted or mutably accessed
+    fn set_change_tick(&mut self, tick: Tick);
+
+    /// The address of a row's component, for callers that only know the component by ID
+    fn get_ptr(&self, at: EntityLocation) -> Option<*const u8>;
+
+    /// The address of a row's component, marking it as changed
+    fn get_mut_ptr(&mut self, at: EntityLocation) -> Option<*mut u8>;
+
+    /// A row's component as Reflect, None unless the component was registered with ECS::register_reflect
+    fn get_reflect(&self, at: EntityLocation) -> Option<&dyn Reflect>;
+
+    /// A row's component as Reflect, marking it as changed
+    fn get_reflect_mut(&mut self, at: EntityLocation) -> Option<&mut dyn Reflect>;
 }
 
+/// Owns a store of the ECS. The store sits in an UnsafeCell so the system scheduler can write to disjoint stores
+/// through a shared ECS.
+pub(crate) struct StoreCell(Box<UnsafeCell<dyn ComponentStoreAny>>);
+
+impl StoreCell {
+    pub fn new<S: ComponentStoreAny>(store: S) -> Self {
+        let value = UnsafeCell::new(store);
+        Self(box value)
+    }
+
+    /// Caller must ensure that the store isn't borrowed elsewhere while the pointer is dereferenced mutably
+    pub fn as_ptr(&self) -> *mut dyn ComponentStoreAny {
+        self.0.get()
+    }
+}
+
+impl Deref for StoreCell {
+    type Target = dyn ComponentStoreAny;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.0.get() }
+    }
+}
+
+impl DerefMut for StoreCell {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.get_mut()
+    }
+}
+
+impl std::fmt::Debug for StoreCell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// When a component was inserted, and when it was last mutably accessed
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ComponentTicks {
+    pub added: Tick,
+    pub changed: Tick,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: Tick) -> Self {
+        Self{ added: tick, changed: tick }
+    }
+
+    pub fn is_added(&self, since: Tick) -> bool {
+        self.added > since
+    }
+
+    pub fn is_changed(&self, since: Tick) -> bool {
+        self.changed > since
+    }
+}
+
+/// Casts to Reflect, kept by stores of components registered with ECS::register_reflect
+pub(crate) struct ReflectFns<T> {
+    get_ref: fn(&T) -> &dyn Reflect,
+    get_mut: fn(&mut T) -> &mut dyn Reflect,
+}
+
+impl<T> std::fmt::Debug for ReflectFns<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ReflectFns")
+    }
+}
+
+/// Stores every component of a type, one column per archetype.
+/// Every column has a matching column of ticks for change detection.
 #[derive(Debug)]
 pub struct ComponentStore<T: Component> {
-    store: GIDStore<T>,
+    chunk_size: ChunkSize,
+    change_tick: Tick,
+    columns: Vec<ChunkyVec<T>>,
+    ticks: Vec<ChunkyVec<ComponentTicks>>,
+    queries: Vec<(QueryID, u8)>,
+    reflect: Option<ReflectFns<T>>,
 }
 
 impl<T: Component> ComponentStoreAny for ComponentStore<T> {
     fn component_id(&self)     -> ComponentID  { T::ID     }
     fn component_id_str(&self) -> &'static str { T::ID_STR }
+    fn layout(&self)           -> Layout       { Layout::new::<T>() }
+
+    fn as_any(&self)         -> &dyn Any     { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn move_row(&mut self, from: EntityLocation, to: ArchetypeID) {
+        let (value, ticks) = self.remove_with_ticks(from);
+        self.insert_with_ticks(to, value, ticks);
+    }
+
+    fn remove_row(&mut self, at: EntityLocation) {
+        self.remove(at);
+    }
+
+    fn take_boxed(&mut self, at: EntityLocation) -> Box<dyn Any> {
+        let value = self.remove(at);
+        box value
+    }
+
+    fn insert_boxed(&mut self, archetype: ArchetypeID, value: Box<dyn Any>) -> usize {
+        self.insert(archetype, Self::unbox(value))
+    }
+
+    fn replace_boxed(&mut self, at: EntityLocation, value: Box<dyn Any>) {
+        self.replace(at, Self::unbox(value));
+    }
+
+    fn set_change_tick(&mut self, tick: Tick) {
+        self.change_tick = tick;
+    }
+
+    fn get_ptr(&self, at: EntityLocation) -> Option<*const u8> {
+        self.get_ref(at).map(|v| v as *const T as *const u8)
+    }
+
+    fn get_mut_ptr(&mut self, at: EntityLocation) -> Option<*mut u8> {
+        self.get_mut(at).map(|v| v as *mut T as *mut u8)
+    }
+
+    fn get_reflect(&self, at: EntityLocation) -> Option<&dyn Reflect> {
+        let reflect = self.reflect.as_ref()?;
+        self.get_ref(at).map(reflect.get_ref)
+    }
+
+    fn get_reflect_mut(&mut self, at: EntityLocation) -> Option<&mut dyn Reflect> {
+        let get_mut = self.reflect.as_ref()?.get_mut;
+        self.get_mut(at).map(get_mut)
+    }
+}
+
+impl<T: Component> QueryUpdater for ComponentStore<T> {
+    fn register_query(&mut self, query: (QueryID, u8)) {
+        self.queries.push(query);
+    }
+
+    fn get_queries(&self) -> &[(QueryID, u8)] {
+        &self.queries
+    }
+
+    fn get_count_hint(&self) -> Option<usize> {
+        Some(self.len())
+    }
 }
 
 impl<T: Component> ComponentStore<T> {
 
     pub fn new(chunk_size: ChunkSize) -> Self {
         Self{
-            store: GIDStore::new(chunk_size)
+            chunk_size,
+            change_tick: Tick::default(),
+            columns: Vec::new(),
+            ticks: Vec::new(),
+            queries: Vec::new(),
+            reflect: None,
         }
     }
 
-    pub fn get_ref(&self, eid: EntityID) -> Option<& T> {
-        self.store.get(eid)
+    pub(crate) fn enable_reflect(&mut self) where T: Reflect {
+        self.reflect = Some(ReflectFns{ get_ref: |v| v, get_mut: |v| v });
+    }
+
+    pub fn get_ref(&self, at: EntityLocation) -> Option<&T> {
+        self.columns.get(at.archetype.get_idx()).and_then(|v| v.get(at.row))
+    }
+
+    /// Mutably borrows a component, marking it as changed
+    pub fn get_mut(&mut self, at: EntityLocation) -> Option<&mut T> {
+        let ticks = self.ticks.get_mut(at.archetype.get_idx()).and_then(|v| v.get_mut(at.row))?;
+        ticks.changed = self.change_tick;
+        self.columns[at.archetype.get_idx()].get_mut(at.row)
+    }
+
+    pub fn get_ticks(&self, at: EntityLocation) -> Option<ComponentTicks> {
+        self.ticks.get(at.archetype.get_idx()).and_then(|v| v.get(at.row)).copied()
+    }
+
+    pub fn contains(&self, at: EntityLocation) -> bool {
+        self.get_ref(at).is_some()
+    }
+
+    /// The column of an archetype, empty if the archetype doesn't hold this component
+    pub fn column(&self, archetype: ArchetypeID) -> Option<&ChunkyVec<T>> {
+        self.columns.get(archetype.get_idx())
+    }
+
+    /// Appends to an archetype's column, returning the row
+    pub fn insert(&mut self, archetype: ArchetypeID, value: T) -> usize {
+        self.insert_with_ticks(archetype, value, ComponentTicks::new(self.change_tick))
     }
 
-    pub fn contains(&mut self, eid: EntityID) -> bool {
-        self.store.contains_key(eid)
+    /// Overwrites a row, marking it as changed and returning the previous value
+    pub fn replace(&mut self, at: EntityLocation, value: T) -> T {
+        let change_tick = self.change_tick;
+        self.column_mut(at.archetype);
+        self.ticks[at.archetype.get_idx()][at.row].changed = change_tick;
+        std::mem::replace(&mut self.columns[at.archetype.get_idx()][at.row], value)
     }
 
-    /*pub fn set(&mut self, eid: EntityID, value: &mut MoveRef) {
-        todo!()
+    /// Swap-removes a row from an archetype's column
+    pub fn remove(&mut self, at: EntityLocation) -> T {
+        self.remove_with_ticks(at).0
+    }
+
+    fn unbox(value: Box<dyn Any>) -> T {
+        match value.downcast::<T>() {
+            Ok(v)  => *v,
+            Err(_) => panic!("Boxed component passed to the ComponentStore of \"{}\" has a different type", T::ID_STR),
+        }
     }
 
-    pub fn get(&mut self, eid: EntityID, value: &mut MoveRef) {
-        todo!()
+    fn insert_with_ticks(&mut self, archetype: ArchetypeID, value: T, ticks: ComponentTicks) -> usize {
+        self.column_mut(archetype).push(value);
+        self.ticks[archetype.get_idx()].push(ticks);
+        return self.ticks[archetype.get_idx()].len() - 1;
     }
 
-    pub fn get_ref(&self, eid: EntityID) -> Option<&dyn ComponentObject> {
-        todo!()
+    fn remove_with_ticks(&mut self, at: EntityLocation) -> (T, ComponentTicks) {
+        let value = self.column_mut(at.archetype).swap_remove(at.row);
+        (value, self.ticks[at.archetype.get_idx()].swap_remove(at.row))
     }
 
-    pub fn get_mut(&mut self, eid: EntityID) -> Option<&mut dyn ComponentObject> {
-        todo!()
+    pub fn is_empty(&self) -> bool {
+        self.columns.iter().all(|v| v.is_empty())
     }
 
-    pub fn has(&self, eid: EntityID) -> bool {
-        todo!()
+    pub fn len(&self) -> usize {
+        self.columns.iter().map(|v| v.len()).sum()
     }
 
-    pub fn remove(&mut self, eid: EntityID) -> bool {
-        todo!()
+    /// Iterates every component in archetype then row order
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.columns.iter().flat_map(|v| v.iter())
     }
 
-    pub fn entities(&self, out: &mut Vec<EntityID>) {
-        todo!()
+    /// Iterates every component in archetype then row order, marking each as changed
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        let change_tick = self.change_tick;
+        self.columns.iter_mut().zip(self.ticks.iter_mut())
+            .flat_map(|(values, ticks)| values.iter_mut().zip(ticks.iter_mut()))
+            .map(move |(value, ticks)| { ticks.changed = change_tick; value })
     }
-    
-    pub fn get_raw_ref(&    self, eid: EntityID) -> Option<&    T> {
-        todo!()
+
+    /// Collects the owner of every component, in the same order as iter
+    pub fn entities(&self, archetypes: &Archetypes, out: &mut Vec<EntityID>) {
+        for (idx, column) in self.columns.iter().enumerate() {
+            if column.is_empty() { continue; }
+            out.extend_from_slice(archetypes.get(ArchetypeID(idx as u32)).entities());
+        }
+    }
+
+    fn column_mut(&mut self, archetype: ArchetypeID) -> &mut ChunkyVec<T> {
+        let idx = archetype.get_idx();
+        if self.columns.len() <= idx {
+            let chunk_size = self.chunk_size;
+            self.columns.resize_with(idx + 1, || ChunkyVec::new(chunk_size));
+            self.ticks.resize_with(idx + 1, || ChunkyVec::new(chunk_size));
+        }
+        &mut self.columns[idx]
     }
 
-    pub fn get_raw_mut(&mut self, eid: EntityID) -> Option<&mut T> {
-        todo!()
-    }*/
 }
\ No newline at end of file
@@ -2,20 +2,168 @@
 ** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
 ** ************************************************************************ */
 
-use arrayvec::ArrayVec;
+use std::marker::PhantomData;
+
 use butterscotch_codegen::generate_tuple_impls;
 
-use crate::{Component, ComponentID, ECS, EntityID, QueryID};
+use crate::{Component, ComponentID, ComponentIDs, ComponentStore, ECS, EntityID, EntityLocation, QueryFilter, QueryID};
+
+// // Access Tracking // //
+
+/// A single component named by a request, and how it is borrowed
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ComponentAccess {
+    pub id: ComponentID,
+    pub id_str: &'static str,
+    pub mutable: bool,
+}
+
+/// Panics if a component is borrowed mutably and named more than once
+pub fn assert_no_aliasing(accesses: &[ComponentAccess]) {
+    for (i, a) in accesses.iter().enumerate() {
+        for b in accesses[i+1..].iter() {
+            if a.id == b.id && (a.mutable || b.mutable) {
+                panic!("Component \"{}\" requested more than once while mutably borrowed", a.id_str);
+            }
+        }
+    }
+}
+
+// // Element types // //
+
+/// Requests a mutable borrow of a component, ie. `((A, Mut<B>), ())` retrieves `(&A, &mut B)`
+pub struct Mut<T: Component>(PhantomData<T>);
+
+/// A retrievable borrow of a single component
+pub trait ComponentRef<'a>: Sized {
+    type Component: Component;
+    const MUTABLE: bool;
+
+    /// Caller must ensure that mutable borrows don't alias
+    unsafe fn fetch(store: *mut ComponentStore<Self::Component>, at: EntityLocation) -> Option<Self>;
+
+    fn access() -> ComponentAccess {
+        ComponentAccess{
+            id:      <Self::Component as Component>::ID,
+            id_str:  <Self::Component as Component>::ID_STR,
+            mutable: Self::MUTABLE,
+        }
+    }
+}
+
+/// Maps an element of an immutable request definition to what it retrieves. Nested tuples retrieve nested tuples,
+/// so `((A, (B, C)), ())` is a single query over all three components retrieving `(&A, (&B, &C))`.
+pub trait ComponentReadRequest<'a> {
+    type Ref: ReqRefComponents<'a>;
+    type OptRef: OptRefComponents<'a>;
+}
+
+/// Maps an element of a mutable request definition to what it retrieves, nesting like ComponentReadRequest
+pub trait ComponentRequest<'a> {
+    type Ref: ReqMutComponents<'a>;
+    type OptRef: OptMutComponents<'a>;
+}
+
+impl<'a, T: Component> ComponentRef<'a> for &'a T {
+    type Component = T;
+    const MUTABLE: bool = false;
+
+    unsafe fn fetch(store: *mut ComponentStore<T>, at: EntityLocation) -> Option<Self> {
+        (*store).get_ref(at)
+    }
+}
+
+impl<'a, T: Component> ComponentRef<'a> for &'a mut T {
+    type Component = T;
+    const MUTABLE: bool = true;
+
+    unsafe fn fetch(store: *mut ComponentStore<T>, at: EntityLocation) -> Option<Self> {
+        (*store).get_mut(at)
+    }
+}
+
+impl<'a, T: Component> ComponentReadRequest<'a> for T {
+    type Ref = &'a T;
+    type OptRef = Option<&'a T>;
+}
+
+impl<'a, T: Component> ComponentRequest<'a> for T {
+    type Ref = &'a T;
+    type OptRef = Option<&'a T>;
+}
+
+impl<'a, T: Component> ComponentRequest<'a> for Mut<T> {
+    type Ref = &'a mut T;
+    type OptRef = Option<&'a mut T>;
+}
 
 // // Storage types // //
+// Implemented for single borrows and tuples of them, nested tuples flatten into one query
 
 pub trait ReqRefComponents<'a> {
     fn retrieve(ecs: &'a ECS, eid: EntityID) -> Option<Self> where Self: Sized;
-    fn ids() -> ArrayVec<[ComponentID; 8]>;
+    fn ids(out: &mut ComponentIDs);
 }
 
 pub trait OptRefComponents<'a> {
     fn retrieve(ecs: &'a ECS, eid: EntityID) -> Self;
+    fn ids(out: &mut ComponentIDs);
+}
+
+/// Mutable requests retrieve through raw store pointers taken once before iterating, so iterating never borrows
+/// the whole ECS while components are mutably borrowed
+pub trait ReqMutComponents<'a> {
+    type State: Copy;
+
+    fn state(ecs: &ECS) -> Self::State;
+
+    /// Caller must ensure the request doesn't alias, see assert_no_aliasing
+    unsafe fn retrieve(state: Self::State, at: EntityLocation) -> Option<Self> where Self: Sized;
+    fn ids(out: &mut ComponentIDs);
+    fn accesses(out: &mut Vec<ComponentAccess>);
+}
+
+pub trait OptMutComponents<'a> {
+    type State: Copy;
+
+    fn state(ecs: &ECS) -> Self::State;
+
+    /// Caller must ensure the request doesn't alias, see assert_no_aliasing
+    unsafe fn retrieve(state: Self::State, at: EntityLocation) -> Self;
+    fn accesses(out: &mut Vec<ComponentAccess>);
+}
+
+impl<'a, T: Component> ReqRefComponents<'a> for &'a T {
+    fn retrieve(ecs: &'a ECS, eid: EntityID) -> Option<Self> { ecs.get_ref::<T>(eid) }
+    fn ids(out: &mut ComponentIDs) { out.push(T::ID); }
+}
+
+impl<'a, T: Component> OptRefComponents<'a> for Option<&'a T> {
+    fn retrieve(ecs: &'a ECS, eid: EntityID) -> Self { ecs.get_ref::<T>(eid) }
+    fn ids(out: &mut ComponentIDs) { out.push(T::ID); }
+}
+
+impl<'a, T: Component> ReqMutComponents<'a> for &'a T {
+    type State = *mut ComponentStore<T>;
+    fn state(ecs: &ECS) -> Self::State { unsafe { ecs.get_store_ptr::<T>() } }
+    unsafe fn retrieve(state: Self::State, at: EntityLocation) -> Option<Self> { Self::fetch(state, at) }
+    fn ids(out: &mut ComponentIDs) { out.push(T::ID); }
+    fn accesses(out: &mut Vec<ComponentAccess>) { out.push(Self::access()); }
+}
+
+impl<'a, T: Component> ReqMutComponents<'a> for &'a mut T {
+    type State = *mut ComponentStore<T>;
+    fn state(ecs: &ECS) -> Self::State { unsafe { ecs.get_store_ptr::<T>() } }
+    unsafe fn retrieve(state: Self::State, at: EntityLocation) -> Option<Self> { Self::fetch(state, at) }
+    fn ids(out: &mut ComponentIDs) { out.push(T::ID); }
+    fn accesses(out: &mut Vec<ComponentAccess>) { out.push(Self::access()); }
+}
+
+impl<'a, R: ComponentRef<'a>> OptMutComponents<'a> for Option<R> {
+    type State = *mut ComponentStore<R::Component>;
+    fn state(ecs: &ECS) -> Self::State { unsafe { ecs.get_store_ptr::<R::Component>() } }
+    unsafe fn retrieve(state: Self::State, at: EntityLocation) -> Self { R::fetch(state, at) }
+    fn accesses(out: &mut Vec<ComponentAccess>) { out.push(R::access()); }
 }
 
 // // User Definiition Helpers // //
@@ -31,27 +179,88 @@ pub trait OptRefComponentsDefinition<'a> {
 pub trait ComponentRequestTupleDefinition<'a> {
     type ReqRefComponentTuple: ReqRefComponents<'a>;
     type OptRefComponentTuple: OptRefComponents<'a>;
+    type Filter: QueryFilter;
+
+    fn query_id() -> QueryID {
+        let mut ids = ComponentIDs::new();
+        Self::ReqRefComponentTuple::ids(&mut ids);
+        QueryID::new::<Self::Filter>(&ids)
+    }
+}
+
+pub trait ReqMutComponentsDefinition<'a> {
+    type TupleType: ReqMutComponents<'a>;
+}
+
+pub trait OptMutComponentsDefinition<'a> {
+    type TupleType: OptMutComponents<'a>;
+}
+
+pub trait ComponentMutRequestTupleDefinition<'a> {
+    type ReqMutComponentTuple: ReqMutComponents<'a>;
+    type OptMutComponentTuple: OptMutComponents<'a>;
+    type Filter: QueryFilter;
+
+    fn query_id() -> QueryID {
+        let mut ids = ComponentIDs::new();
+        Self::ReqMutComponentTuple::ids(&mut ids);
+        QueryID::new::<Self::Filter>(&ids)
+    }
+
+    fn accesses() -> Vec<ComponentAccess> {
+        let mut result = Vec::new();
+        Self::ReqMutComponentTuple::accesses(&mut result);
+        Self::OptMutComponentTuple::accesses(&mut result);
+        return result;
+    }
+}
+
+impl<'a, T: ReqRefComponentsDefinition<'a>, U: OptRefComponentsDefinition<'a>, V: QueryFilter> ComponentRequestTupleDefinition<'a> for (T,U,V,) {
+    type ReqRefComponentTuple = T::TupleType;
+    type OptRefComponentTuple = U::TupleType;
+    type Filter = V;
 }
 
 impl<'a, T: ReqRefComponentsDefinition<'a>, U: OptRefComponentsDefinition<'a>> ComponentRequestTupleDefinition<'a> for (T,U,) {
     type ReqRefComponentTuple = T::TupleType;
     type OptRefComponentTuple = U::TupleType;
+    type Filter = ();
 }
 
 impl<'a, T: ReqRefComponentsDefinition<'a>> ComponentRequestTupleDefinition<'a> for (T,) {
     type ReqRefComponentTuple = T::TupleType;
     type OptRefComponentTuple = ();
+    type Filter = ();
+}
+
+impl<'a, T: ReqMutComponentsDefinition<'a>, U: OptMutComponentsDefinition<'a>, V: QueryFilter> ComponentMutRequestTupleDefinition<'a> for (T,U,V,) {
+    type ReqMutComponentTuple = T::TupleType;
+    type OptMutComponentTuple = U::TupleType;
+    type Filter = V;
+}
+
+impl<'a, T: ReqMutComponentsDefinition<'a>, U: OptMutComponentsDefinition<'a>> ComponentMutRequestTupleDefinition<'a> for (T,U,) {
+    type ReqMutComponentTuple = T::TupleType;
+    type OptMutComponentTuple = U::TupleType;
+    type Filter = ();
+}
+
+impl<'a, T: ReqMutComponentsDefinition<'a>> ComponentMutRequestTupleDefinition<'a> for (T,) {
+    type ReqMutComponentTuple = T::TupleType;
+    type OptMutComponentTuple = ();
+    type Filter = ();
 }
 
 // // Unit Tuple Impl // //
 
 impl<'a> ReqRefComponents<'a> for () {
     fn retrieve(_ecs: &'a ECS, _eid: EntityID) -> Option<Self> where Self: Sized { Some(()) }
-    fn ids() -> ArrayVec<[ComponentID; 8]> { Default::default() }
+    fn ids(_out: &mut ComponentIDs) {}
 }
 
 impl<'a> OptRefComponents<'a> for () {
     fn retrieve(_ecs: &'a ECS, _eid: EntityID) -> Self where Self: Sized { () }
+    fn ids(_out: &mut ComponentIDs) {}
 }
 
 impl<'a> ReqRefComponentsDefinition<'a> for () {
@@ -62,38 +271,127 @@ impl<'a> OptRefComponentsDefinition<'a> for () {
     type TupleType = ();
 }
 
+impl<'a> ReqMutComponents<'a> for () {
+    type State = ();
+    fn state(_ecs: &ECS) -> Self::State {}
+    unsafe fn retrieve(_state: Self::State, _at: EntityLocation) -> Option<Self> where Self: Sized { Some(()) }
+    fn ids(_out: &mut ComponentIDs) {}
+    fn accesses(_out: &mut Vec<ComponentAccess>) {}
+}
+
+impl<'a> OptMutComponents<'a> for () {
+    type State = ();
+    fn state(_ecs: &ECS) -> Self::State {}
+    unsafe fn retrieve(_state: Self::State, _at: EntityLocation) -> Self where Self: Sized { () }
+    fn accesses(_out: &mut Vec<ComponentAccess>) {}
+}
+
+impl<'a> ReqMutComponentsDefinition<'a> for () {
+    type TupleType = ();
+}
+
+impl<'a> OptMutComponentsDefinition<'a> for () {
+    type TupleType = ();
+}
+
 // // Impl Tuples // //
 
-generate_tuple_impls!(8, r"
-    impl<'a, %{%TR: Component,%}>
+generate_tuple_impls!(16, r"
+    impl<'a, %{%TR: ComponentReadRequest<'a>,%}>
+    ComponentReadRequest<'a> for (%{%TR, %}) {
+        type Ref = (%{%TR::Ref, %});
+        type OptRef = (%{%TR::OptRef, %});
+    }
+
+    impl<'a, %{%TR: ComponentReadRequest<'a>,%}>
     ReqRefComponentsDefinition<'a> for (%{%TR, %}) {
-        type TupleType = (%{&'a %TR, %});
+        type TupleType = (%{%TR::Ref, %});
     }
 
-    impl<'a, %{%TR: Component,%}>
-    ReqRefComponents<'a> for (%{&'a %TR, %}) {
+    impl<'a, %{%TR: ReqRefComponents<'a>,%}>
+    ReqRefComponents<'a> for (%{%TR, %}) {
         fn retrieve(ecs: &'a ECS, eid: EntityID) -> Option<Self> {Some((%{
-            ecs.get_store_ref::<%TR>().get_ref(eid)?,%}
+            %TR::retrieve(ecs, eid)?,%}
         ))}
 
-        fn ids() -> QueryID {
-            let mut result = QueryID::new();%{
-            result.push(%TR::ID);%}
-            result
+        fn ids(out: &mut ComponentIDs) {%{
+            %TR::ids(out);%}
         }
     }
 ");
 
-generate_tuple_impls!(8, r"
-    impl<'a, %{%TR: Component,%}>
+generate_tuple_impls!(16, r"
+    impl<'a, %{%TR: ComponentReadRequest<'a>,%}>
     OptRefComponentsDefinition<'a> for (%{%TR, %}) {
-        type TupleType = (%{Option<&'a %TR>, %});
+        type TupleType = (%{%TR::OptRef, %});
     }
 
-    impl<'a, %{%TR: Component,%}>
-    OptRefComponents<'a> for (%{Option<&'a %TR>, %}) {
+    impl<'a, %{%TR: OptRefComponents<'a>,%}>
+    OptRefComponents<'a> for (%{%TR, %}) {
         fn retrieve(ecs: &'a ECS, eid: EntityID) -> Self {(%{
-            ecs.get_store_ref::<%TR>().get_ref(eid),%}
+            %TR::retrieve(ecs, eid),%}
         )}
+
+        fn ids(out: &mut ComponentIDs) {%{
+            %TR::ids(out);%}
+        }
+    }
+");
+
+generate_tuple_impls!(16, r"
+    impl<'a, %{%TR: ComponentRequest<'a>,%}>
+    ComponentRequest<'a> for (%{%TR, %}) {
+        type Ref = (%{%TR::Ref, %});
+        type OptRef = (%{%TR::OptRef, %});
+    }
+
+    impl<'a, %{%TR: ComponentRequest<'a>,%}>
+    ReqMutComponentsDefinition<'a> for (%{%TR, %}) {
+        type TupleType = (%{%TR::Ref, %});
+    }
+
+    impl<'a, %{%TR: ReqMutComponents<'a>,%}>
+    ReqMutComponents<'a> for (%{%TR, %}) {
+        type State = (%{%TR::State, %});
+
+        fn state(ecs: &ECS) -> Self::State {(%{
+            %TR::state(ecs),%}
+        )}
+
+        unsafe fn retrieve(state: Self::State, at: EntityLocation) -> Option<Self> {Some((%{
+            %TR::retrieve(state.%VI, at)?,%}
+        ))}
+
+        fn ids(out: &mut ComponentIDs) {%{
+            %TR::ids(out);%}
+        }
+
+        fn accesses(out: &mut Vec<ComponentAccess>) {%{
+            %TR::accesses(out);%}
+        }
+    }
+");
+
+generate_tuple_impls!(16, r"
+    impl<'a, %{%TR: ComponentRequest<'a>,%}>
+    OptMutComponentsDefinition<'a> for (%{%TR, %}) {
+        type TupleType = (%{%TR::OptRef, %});
+    }
+
+    impl<'a, %{%TR: OptMutComponents<'a>,%}>
+    OptMutComponents<'a> for (%{%TR, %}) {
+        type State = (%{%TR::State, %});
+
+        fn state(ecs: &ECS) -> Self::State {(%{
+            %TR::state(ecs),%}
+        )}
+
+        unsafe fn retrieve(state: Self::State, at: EntityLocation) -> Self {(%{
+            %TR::retrieve(state.%VI, at),%}
+        )}
+
+        fn accesses(out: &mut Vec<ComponentAccess>) {%{
+            %TR::accesses(out);%}
+        }
     }
-");
\ No newline at end of file
+");
@@ -0,0 +1,462 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::{alloc::{self, Layout}, any::Any, fmt, ptr::{self, NonNull}};
+
+use butterscotch_common::container::{ChunkSize, ChunkyVec};
+
+use crate::{ArchetypeID, Archetypes, ComponentID, ComponentStoreAny, ComponentTicks, EntityID, EntityLocation, QueryID, QueryUpdater, Reflect, Tick};
+
+type DropFn  = unsafe fn(*mut u8);
+type CloneFn = unsafe fn(*const u8, *mut u8);
+type DebugFn = unsafe fn(*const u8, &mut fmt::Formatter) -> fmt::Result;
+
+/// Describes a component defined at runtime, ie. by a script, stored as raw bytes rather than a Rust type.
+/// Values are shared between threads like any other component, so they must be safe to send and share.
+#[derive(Clone, Copy)]
+pub struct DynamicComponentInfo {
+    name: &'static str,
+    layout: Layout,
+    drop: Option<DropFn>,
+    clone: Option<CloneFn>,
+    debug: Option<DebugFn>,
+}
+
+impl fmt::Debug for DynamicComponentInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynamicComponentInfo")
+            .field("name", &self.name)
+            .field("size", &self.layout.size())
+            .field("align", &self.layout.align())
+            .finish()
+    }
+}
+
+impl DynamicComponentInfo {
+
+    /// A plain data component. The name plays the part of ID_STR, so it should be namespaced, and is leaked
+    /// as components live for the rest of the program. Panics if the alignment isn't a power of two.
+    pub fn new(name: &str, size: usize, align: usize) -> Self {
+        let layout = Layout::from_size_align(size, align).expect("Invalid dynamic component layout");
+        Self{ name: Box::leak(name.into()), layout, drop: None, clone: None, debug: None }
+    }
+
+    /// Describes a Rust type as a dynamic component, for hosts mirroring what scripts define
+    pub fn of<T: Any + Clone + fmt::Debug + Send + Sync>(name: &str) -> Self {
+        let result = Self::new(name, std::mem::size_of::<T>(), std::mem::align_of::<T>());
+        unsafe {
+            return result
+                .with_drop(|v| ptr::drop_in_place(v as *mut T))
+                .with_clone(|v, out| ptr::write(out as *mut T, (*(v as *const T)).clone()))
+                .with_debug(|v, f| fmt::Debug::fmt(&*(v as *const T), f));
+        }
+    }
+
+    /// Runs when a value is dropped. Must be sound for every value of the component.
+    pub unsafe fn with_drop(mut self, drop: DropFn) -> Self {
+        self.drop = Some(drop);
+        return self;
+    }
+
+    /// Writes a copy of the first value into the uninitialized second. Must be sound for every value of the component.
+    pub unsafe fn with_clone(mut self, clone: CloneFn) -> Self {
+        self.clone = Some(clone);
+        return self;
+    }
+
+    /// Formats a value. Must be sound for every value of the component.
+    pub unsafe fn with_debug(mut self, debug: DebugFn) -> Self {
+        self.debug = Some(debug);
+        return self;
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn id(&self) -> ComponentID {
+        ComponentID::from_name(self.name)
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Plain data can be built from arbitrary bytes
+    pub fn is_plain_data(&self) -> bool {
+        self.drop.is_none()
+    }
+
+    pub fn can_clone(&self) -> bool {
+        self.clone.is_some()
+    }
+
+    /// The size of a value once padded to its alignment, the stride of a column
+    fn stride(&self) -> usize {
+        self.layout.pad_to_align().size()
+    }
+}
+
+// // Values // //
+
+/// An owned value of a dynamic component, attached with ECS::attach_dynamic or Commands::attach_dynamic
+pub struct DynamicValue {
+    info: DynamicComponentInfo,
+    data: NonNull<u8>,
+}
+
+unsafe impl Send for DynamicValue {}
+unsafe impl Sync for DynamicValue {}
+
+impl DynamicValue {
+
+    /// Copies bytes into a new value. Panics if the length doesn't match the size of the component,
+    /// or if the component has a drop function, as arbitrary bytes might not be a valid value of it.
+    pub fn from_bytes(info: &DynamicComponentInfo, bytes: &[u8]) -> Self {
+        assert!(info.is_plain_data(), "Dynamic component \"{}\" isn't plain data, build it with from_raw", info.name);
+        assert_eq!(bytes.len(), info.layout.size(), "Wrong number of bytes for dynamic component \"{}\"", info.name);
+        unsafe { return Self::from_raw(info, bytes.as_ptr()); }
+    }
+
+    /// Moves the value at `data` into a new value, the caller must treat `data` as moved out of
+    pub unsafe fn from_raw(info: &DynamicComponentInfo, data: *const u8) -> Self {
+        let result = Self{ info: *info, data: allocate(info.layout) };
+        ptr::copy_nonoverlapping(data, result.data.as_ptr(), info.layout.size());
+        return result;
+    }
+
+    /// Moves a Rust value into a new value, the component's functions must expect a T. Panics if the layouts differ.
+    pub unsafe fn from_value<T>(info: &DynamicComponentInfo, value: T) -> Self {
+        assert!(info.layout == Layout::new::<T>(), "Layout of {} doesn't match dynamic component \"{}\"", std::any::type_name::<T>(), info.name);
+        let value = std::mem::ManuallyDrop::new(value);
+        return Self::from_raw(info, &*value as *const T as *const u8);
+    }
+
+    pub fn id(&self) -> ComponentID {
+        self.info.id()
+    }
+
+    pub fn info(&self) -> &DynamicComponentInfo {
+        &self.info
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.data.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.data.as_ptr()
+    }
+
+    /// A copy of the value, if the component has a clone function
+    pub fn try_clone(&self) -> Option<Self> { unsafe {
+        let clone = self.info.clone?;
+        let result = Self{ info: self.info, data: allocate(self.info.layout) };
+        clone(self.data.as_ptr(), result.data.as_ptr());
+        return Some(result);
+    }}
+
+    /// Moves the value into `out` without dropping it
+    unsafe fn write_into(self, out: *mut u8) {
+        ptr::copy_nonoverlapping(self.data.as_ptr(), out, self.info.layout.size());
+        deallocate(self.data, self.info.layout);
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for DynamicValue {
+    fn drop(&mut self) { unsafe {
+        if let Some(drop) = self.info.drop { drop(self.data.as_ptr()); }
+        deallocate(self.data, self.info.layout);
+    }}
+}
+
+impl fmt::Debug for DynamicValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        DebugValue(&self.info, self.data.as_ptr()).fmt(f)
+    }
+}
+
+struct DebugValue<'a>(&'a DynamicComponentInfo, *const u8);
+
+impl<'a> fmt::Debug for DebugValue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.debug {
+            Some(debug) => unsafe { debug(self.1, f) },
+            None        => write!(f, "{} {{ .. }}", self.0.name),
+        }
+    }
+}
+
+fn allocate(layout: Layout) -> NonNull<u8> {
+    if layout.size() == 0 { return NonNull::new(layout.align() as *mut u8).unwrap(); }
+    match NonNull::new(unsafe { alloc::alloc(layout) }) {
+        Some(v) => v,
+        None    => alloc::handle_alloc_error(layout),
+    }
+}
+
+unsafe fn deallocate(data: NonNull<u8>, layout: Layout) {
+    if layout.size() != 0 { alloc::dealloc(data.as_ptr(), layout); }
+}
+
+// // Columns // //
+
+/// A contiguous array of values only known by their layout
+struct DynamicColumn {
+    info: DynamicComponentInfo,
+    data: NonNull<u8>,
+    len: usize,
+    capacity: usize,
+}
+
+impl DynamicColumn {
+
+    fn new(info: DynamicComponentInfo) -> Self {
+        let capacity = if info.stride() == 0 { usize::MAX } else { 0 };
+        Self{ info, data: allocate(Layout::from_size_align(0, info.layout.align()).unwrap()), len: 0, capacity }
+    }
+
+    fn get(&self, row: usize) -> Option<*mut u8> {
+        if row >= self.len { return None; }
+        unsafe { return Some(self.data.as_ptr().add(row * self.info.stride())); }
+    }
+
+    fn push(&mut self, value: DynamicValue) -> usize {
+        if self.len == self.capacity { self.grow(); }
+        self.len += 1;
+        unsafe { value.write_into(self.get(self.len - 1).unwrap()); }
+        return self.len - 1;
+    }
+
+    /// Moves the last value into the removed row's place
+    fn swap_remove(&mut self, row: usize) -> DynamicValue { unsafe {
+        let removed = self.get(row).expect("Dynamic column row out of bounds");
+        let result = DynamicValue::from_raw(&self.info, removed);
+        let last = self.get(self.len - 1).unwrap();
+        if last != removed { ptr::copy_nonoverlapping(last, removed, self.info.layout.size()); }
+        self.len -= 1;
+        return result;
+    }}
+
+    fn grow(&mut self) {
+        let stride = self.info.stride();
+        let capacity = std::cmp::max(8, self.capacity * 2);
+        let layout = Layout::from_size_align(stride * capacity, self.info.layout.align()).expect("Dynamic column too large");
+        let data = match self.capacity {
+            0 => unsafe { alloc::alloc(layout) },
+            _ => unsafe { alloc::realloc(self.data.as_ptr(), self.layout(), layout.size()) },
+        };
+        self.data = NonNull::new(data).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        self.capacity = capacity;
+    }
+
+    fn layout(&self) -> Layout {
+        Layout::from_size_align(self.info.stride() * self.capacity, self.info.layout.align()).unwrap()
+    }
+}
+
+impl Drop for DynamicColumn {
+    fn drop(&mut self) { unsafe {
+        if let Some(drop) = self.info.drop {
+            for row in 0..self.len { drop(self.get(row).unwrap()); }
+        }
+        if self.info.stride() != 0 && self.capacity != 0 { alloc::dealloc(self.data.as_ptr(), self.layout()); }
+    }}
+}
+
+// // Store // //
+
+/// Stores every value of a dynamic component, one column per archetype.
+/// Values are reached through pointers, by ComponentStoreAny::get_ptr or ECS::get_ptr.
+pub struct DynamicStore {
+    info: DynamicComponentInfo,
+    change_tick: Tick,
+    columns: Vec<DynamicColumn>,
+    ticks: Vec<ChunkyVec<ComponentTicks>>,
+    queries: Vec<(QueryID, u8)>,
+}
+
+unsafe impl Send for DynamicStore {}
+unsafe impl Sync for DynamicStore {}
+
+impl fmt::Debug for DynamicStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynamicStore")
+            .field("info", &self.info)
+            .field("change_tick", &self.change_tick)
+            .field("columns", &self.columns.iter().map(|column| {
+                (0..column.len).map(|row| DebugValue(&self.info, column.get(row).unwrap())).collect::<Vec<_>>()
+            }).collect::<Vec<_>>())
+            .field("queries", &self.queries)
+            .finish()
+    }
+}
+
+impl ComponentStoreAny for DynamicStore {
+    fn component_id(&self)     -> ComponentID  { self.info.id()  }
+    fn component_id_str(&self) -> &'static str { self.info.name  }
+    fn layout(&self)           -> Layout       { self.info.layout }
+
+    fn as_any(&self)         -> &dyn Any     { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn move_row(&mut self, from: EntityLocation, to: ArchetypeID) {
+        let value = self.column_mut(from.archetype).swap_remove(from.row);
+        let ticks = self.ticks[from.archetype.get_idx()].swap_remove(from.row);
+        self.insert_with_ticks(to, value, ticks);
+    }
+
+    fn remove_row(&mut self, at: EntityLocation) {
+        self.remove(at);
+    }
+
+    fn take_boxed(&mut self, at: EntityLocation) -> Box<dyn Any> {
+        let value = self.remove(at);
+        box value
+    }
+
+    fn insert_boxed(&mut self, archetype: ArchetypeID, value: Box<dyn Any>) -> usize {
+        let value = self.unbox(value);
+        self.insert(archetype, value)
+    }
+
+    fn replace_boxed(&mut self, at: EntityLocation, value: Box<dyn Any>) {
+        let value = self.unbox(value);
+        self.replace(at, value);
+    }
+
+    fn set_change_tick(&mut self, tick: Tick) {
+        self.change_tick = tick;
+    }
+
+    fn get_ptr(&self, at: EntityLocation) -> Option<*const u8> {
+        self.columns.get(at.archetype.get_idx()).and_then(|v| v.get(at.row)).map(|v| v as *const u8)
+    }
+
+    fn get_mut_ptr(&mut self, at: EntityLocation) -> Option<*mut u8> {
+        let ticks = self.ticks.get_mut(at.archetype.get_idx()).and_then(|v| v.get_mut(at.row))?;
+        ticks.changed = self.change_tick;
+        self.columns[at.archetype.get_idx()].get(at.row)
+    }
+
+    fn get_reflect(&self, _at: EntityLocation) -> Option<&dyn Reflect> {
+        None
+    }
+
+    fn get_reflect_mut(&mut self, _at: EntityLocation) -> Option<&mut dyn Reflect> {
+        None
+    }
+}
+
+impl QueryUpdater for DynamicStore {
+    fn register_query(&mut self, query: (QueryID, u8)) {
+        self.queries.push(query);
+    }
+
+    fn get_queries(&self) -> &[(QueryID, u8)] {
+        &self.queries
+    }
+
+    fn get_count_hint(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl DynamicStore {
+
+    pub fn new(info: DynamicComponentInfo) -> Self {
+        Self{ info, change_tick: Tick::default(), columns: Vec::new(), ticks: Vec::new(), queries: Vec::new() }
+    }
+
+    pub fn info(&self) -> &DynamicComponentInfo {
+        &self.info
+    }
+
+    pub fn get_ticks(&self, at: EntityLocation) -> Option<ComponentTicks> {
+        self.ticks.get(at.archetype.get_idx()).and_then(|v| v.get(at.row)).copied()
+    }
+
+    pub fn contains(&self, at: EntityLocation) -> bool {
+        self.get_ptr(at).is_some()
+    }
+
+    /// A copy of a row's value, if the component has a clone function
+    pub fn clone_value(&self, at: EntityLocation) -> Option<DynamicValue> { unsafe {
+        let clone = self.info.clone?;
+        let source = self.get_ptr(at)?;
+        let result = DynamicValue{ info: self.info, data: allocate(self.info.layout) };
+        clone(source, result.data.as_ptr());
+        return Some(result);
+    }}
+
+    /// Appends to an archetype's column, returning the row. Panics if the value belongs to another component.
+    pub fn insert(&mut self, archetype: ArchetypeID, value: DynamicValue) -> usize {
+        self.insert_with_ticks(archetype, value, ComponentTicks::new(self.change_tick))
+    }
+
+    /// Overwrites a row, marking it as changed and returning the previous value
+    pub fn replace(&mut self, at: EntityLocation, value: DynamicValue) -> DynamicValue { unsafe {
+        self.check(&value);
+        let change_tick = self.change_tick;
+        self.column_mut(at.archetype);
+        self.ticks[at.archetype.get_idx()][at.row].changed = change_tick;
+
+        let target = self.columns[at.archetype.get_idx()].get(at.row).expect("Dynamic column row out of bounds");
+        let result = DynamicValue::from_raw(&self.info, target);
+        value.write_into(target);
+        return result;
+    }}
+
+    /// Swap-removes a row from an archetype's column
+    pub fn remove(&mut self, at: EntityLocation) -> DynamicValue {
+        let value = self.column_mut(at.archetype).swap_remove(at.row);
+        self.ticks[at.archetype.get_idx()].swap_remove(at.row);
+        return value;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.iter().all(|v| v.len == 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.iter().map(|v| v.len).sum()
+    }
+
+    /// Collects the owner of every value, in archetype then row order
+    pub fn entities(&self, archetypes: &Archetypes, out: &mut Vec<EntityID>) {
+        for (idx, column) in self.columns.iter().enumerate() {
+            if column.len == 0 { continue; }
+            out.extend_from_slice(archetypes.get(ArchetypeID(idx as u32)).entities());
+        }
+    }
+
+    fn unbox(&self, value: Box<dyn Any>) -> DynamicValue {
+        match value.downcast::<DynamicValue>() {
+            Ok(v)  => *v,
+            Err(_) => panic!("Boxed component passed to the DynamicStore of \"{}\" isn't a DynamicValue", self.info.name),
+        }
+    }
+
+    fn check(&self, value: &DynamicValue) {
+        assert!(value.id() == self.info.id(), "Value of \"{}\" passed to the DynamicStore of \"{}\"", value.info.name, self.info.name);
+    }
+
+    fn insert_with_ticks(&mut self, archetype: ArchetypeID, value: DynamicValue, ticks: ComponentTicks) -> usize {
+        self.check(&value);
+        self.column_mut(archetype).push(value);
+        self.ticks[archetype.get_idx()].push(ticks);
+        return self.ticks[archetype.get_idx()].len() - 1;
+    }
+
+    fn column_mut(&mut self, archetype: ArchetypeID) -> &mut DynamicColumn {
+        let idx = archetype.get_idx();
+        if self.columns.len() <= idx {
+            let info = self.info;
+            self.columns.resize_with(idx + 1, || DynamicColumn::new(info));
+            self.ticks.resize_with(idx + 1, || ChunkyVec::new(ChunkSize::Elements(1024)));
+        }
+        &mut self.columns[idx]
+    }
+}
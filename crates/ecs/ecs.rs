@@ -2,36 +2,1302 @@
 ** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
 ** ************************************************************************ */
 
-use std::{collections::HashMap};
+use std::{any::Any, collections::{HashMap, HashSet}};
 
-use butterscotch_common::{container::ChunkSize, utility::{downcast_mut_unchecked, downcast_ref_unchecked}};
+use bincode::Options;
+use serde::{Deserializer, Serialize, Serializer, de::DeserializeSeed};
 
-use crate::{BadIntHasher, Component, ComponentID, ComponentStore, ComponentStoreAny};
+use butterscotch_common::{container::{ChunkSize, GIDRegistry}, utility::{downcast_mut_unchecked, downcast_ref_unchecked}};
 
-#[derive(Debug, Default)]
+use crate::{ArchetypeID, Archetypes, BadIntHasher, Bundle, BundleWriter, CascadeEdge, CascadeError, Children, Component, ComponentID, ComponentIDs, ComponentMutRequestTupleDefinition, ComponentRequestTupleDefinition, Command, CommandTarget, Commands, ComponentAccess, ComponentStorage, ComponentStore, ComponentStoreAny, ComponentTicks, DynamicComponentInfo, DynamicStore, DynamicValue, EntityID, EntityMap, Event, EventChannelAny, EventID, EventReader, EventRecord, Events, Mutation, MutationLog, OptMutComponents, LoadedEntity, LoadedRelation, LoadedWorld, OptRefComponents, Parent, Prefab, QueryContainer, QueryFilter, QueryID, ReactiveSystem, Reflect, ReflectError, Relation, RelationID, RelationStore, RelationStoreAny, ReqMutComponents, ReqRefComponents, Resource, Resources, SerializationError, SerializeComponent, SerializeRelation, Serializers, StoreCell, Subscriptions, SubscriptionID, SystemID, Systems, Tick, Trigger, TypeRegistry, WorldRef, WorldSeed, assert_no_aliasing, assert_not_hierarchy, find_cascade_path};
+
+#[derive(Debug)]
 pub struct ECS {
-    component_stores: HashMap<ComponentID, Box<dyn ComponentStoreAny>, BadIntHasher>
+    entities: GIDRegistry,
+    archetypes: Archetypes,
+    component_stores: HashMap<ComponentID, StoreCell, BadIntHasher>,
+    queries: QueryContainer,
+    change_tick: Tick,
+    last_run: Tick,
+    systems: Systems,
+    mutations: MutationLog,
+    event_channels: HashMap<EventID, Box<dyn EventChannelAny>, BadIntHasher>,
+    max_cascade_depth: usize,
+    subscriptions: Subscriptions,
+    resources: Resources,
+    relation_stores: HashMap<RelationID, Box<dyn RelationStoreAny>, BadIntHasher>,
+    serializers: Serializers,
+    type_registry: TypeRegistry,
+}
+
+impl Default for ECS {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ECS {
 
+    pub fn new() -> Self {
+        let mut result = Self{
+            entities: Default::default(),
+            archetypes: Default::default(),
+            component_stores: Default::default(),
+            queries: Default::default(),
+            change_tick: Tick(1), // Leave room for "since the start" queries
+            last_run: Tick::default(),
+            systems: Default::default(),
+            mutations: Default::default(),
+            event_channels: Default::default(),
+            max_cascade_depth: 16,
+            subscriptions: Default::default(),
+            resources: Default::default(),
+            relation_stores: Default::default(),
+            serializers: Default::default(),
+            type_registry: Default::default(),
+        };
+        result.register_component::<Parent>(ChunkSize::Elements(1024));
+        result.register_component::<Children>(ChunkSize::Elements(1024));
+        result.register_serializable::<Parent>();
+        result.register_serializable::<Children>();
+        return result;
+    }
+
     pub fn register_component<T: Component>(&mut self, chunk_size: ChunkSize) {
-        let result = self.component_stores.insert(T::ID, box ComponentStore::<T>::new(chunk_size));
-        assert!(result.is_none(), "ComponentID({}) conflict between \"{}\" and \"{}\"", T::ID.0, T::ID_STR, result.unwrap().component_id_str());
+        let mut store = match T::STORAGE {
+            ComponentStorage::Table => ComponentStore::<T>::new(chunk_size),
+        };
+        store.set_change_tick(self.change_tick);
+        let result = self.component_stores.insert(T::ID, StoreCell::new(store));
+        assert!(result.is_none(), "ComponentID({}) conflict between \"{}\" and \"{}\", rename one of them", T::ID.0, T::ID_STR, result.unwrap().component_id_str());
+    }
+
+    /// Registers a component with the chunk size it declares
+    pub fn register_component_default<T: Component>(&mut self) {
+        self.register_component::<T>(T::CHUNK_SIZE);
     }
 
     pub fn get_store_ref<'a, T: Component + 'static>(&'a self) -> &'a ComponentStore<T> { unsafe { 
         let store = self.component_stores
             .get(&T::ID)
             .expect(&format!("ComponentStore not registered for \"{}\"", std::any::type_name::<T>()));
-        downcast_ref_unchecked::<ComponentStore<T>>(store) // Assuming that typeid doesn't collide (it "can") we don't need to check before casting
+        downcast_ref_unchecked::<ComponentStore<T>>(store.as_any()) // Assuming that typeid doesn't collide (it "can") we don't need to check before casting
     }}
 
     pub fn get_store_mut<'a, T: Component>(&'a mut self) -> &'a mut ComponentStore<T> { unsafe { 
         let store = self.component_stores
             .get_mut(&T::ID)
             .expect(&format!("ComponentStore not registered for \"{}\"", std::any::type_name::<T>()));
-        downcast_mut_unchecked::<ComponentStore<T>>(store) // Assuming that typeid doesn't collide (it "can") we don't need to check before casting
+        downcast_mut_unchecked::<ComponentStore<T>>(store.as_any_mut()) // Assuming that typeid doesn't collide (it "can") we don't need to check before casting
+    }}
+
+    /// The store of any component, typed or dynamic
+    pub fn get_store_any(&self, id: ComponentID) -> Option<&dyn ComponentStoreAny> {
+        self.component_stores.get(&id).map(|v| &**v)
+    }
+
+    /// Caller must ensure that the store isn't borrowed elsewhere while the pointer is dereferenced mutably
+    pub(crate) unsafe fn get_store_ptr<T: Component>(&self) -> *mut ComponentStore<T> {
+        let store = self.component_stores
+            .get(&T::ID)
+            .expect(&format!("ComponentStore not registered for \"{}\"", std::any::type_name::<T>()));
+        store.as_ptr() as *mut ComponentStore<T> // Same assumption as get_store_ref
+    }
+
+    // // Entity Lifecycle // //
+
+    pub fn spawn(&mut self) -> EntityID {
+        let eid = self.entities.acquire();
+        self.archetypes.insert(eid, ArchetypeID::EMPTY);
+        self.mutations.record(Trigger::Spawn, eid);
+        return eid;
+    }
+
+    /// Destroys an entity, its descendants and all of their components, returns false if the entity was already dead
+    pub fn despawn(&mut self, eid: EntityID) -> bool {
+        let mut touched = HashMap::default();
+        let result = self.despawn_recursive(&mut touched, eid);
+        self.catch_up_touched(touched);
+        return result;
+    }
+
+    pub fn is_alive(&self, eid: EntityID) -> bool {
+        self.entities.contains_key(eid)
+    }
+
+    /// Attaches a component, returning the previous value if the entity already had one.
+    /// Panics for Parent and Children, see set_parent.
+    pub fn attach<T: Component>(&mut self, eid: EntityID, value: T) -> Option<T> {
+        assert_not_hierarchy(T::ID);
+        let result = self.attach_untracked(eid, value);
+        if result.is_none() { self.update_presence(eid, T::ID, true); }
+        return result;
+    }
+
+    /// Detaches a component, returning it if the entity had one
+    pub fn detach<T: Component>(&mut self, eid: EntityID) -> Option<T> {
+        assert_not_hierarchy(T::ID);
+        let result = self.detach_untracked::<T>(eid);
+        if result.is_some() { self.update_presence(eid, T::ID, false); }
+        return result;
+    }
+
+    /// Attaches a boxed component by ID, returning false if the entity already had one and it was replaced instead
+    pub fn attach_boxed(&mut self, eid: EntityID, id: ComponentID, value: Box<dyn Any>) -> bool {
+        assert_not_hierarchy(id);
+        let result = self.attach_boxed_untracked(eid, id, value);
+        if result { self.update_presence(eid, id, true); }
+        return result;
+    }
+
+    /// Detaches and drops a component by ID, returning false if the entity didn't have one
+    pub fn detach_id(&mut self, eid: EntityID, id: ComponentID) -> bool {
+        assert_not_hierarchy(id);
+        let result = self.detach_id_untracked(eid, id);
+        if result { self.update_presence(eid, id, false); }
+        return result;
+    }
+
+    // // Bundles // //
+
+    pub fn spawn_bundle<B: Bundle>(&mut self, bundle: B) -> EntityID {
+        let eid = self.spawn();
+        self.insert_bundle(eid, bundle);
+        return eid;
+    }
+
+    /// Attaches every component of a bundle with a single archetype move, replacing any the entity already has
+    pub fn insert_bundle<B: Bundle>(&mut self, eid: EntityID, bundle: B) {
+        let source = self.archetypes.location(eid).expect("Attempt to attach a bundle to a dead entity");
+        let existing = self.archetypes.get(source.archetype).components().iter().copied().collect::<ComponentIDs>();
+        let mut ids = ComponentIDs::new();
+        B::ids(&mut ids);
+
+        let mut target = source.archetype;
+        for (i, id) in ids.iter().enumerate() {
+            let store = self.component_stores.get(id).unwrap_or_else(|| panic!("ComponentStore not registered for ComponentID({})", id.0));
+            assert!(!ids[..i].contains(id), "Component \"{}\" appears more than once in a bundle", store.component_id_str());
+            assert_not_hierarchy(*id);
+            if !existing.contains(id) { target = self.archetypes.with_component(target, *id); }
+        }
+
+        if target != source.archetype { self.relocate(eid, target); }
+        bundle.write(&mut BundleWriter{ ecs: self, eid, existing: &existing });
+        for id in ids.iter().filter(|v| !existing.contains(*v)) {
+            self.update_presence(eid, *id, true);
+        }
+    }
+
+    /// Spawns a prefab along with its child prefabs, returning the root
+    pub fn instantiate(&mut self, prefab: &Prefab) -> EntityID {
+        prefab.spawn(self)
+    }
+
+    /// Spawns a prefab, then overrides components of the root
+    pub fn instantiate_with<B: Bundle>(&mut self, prefab: &Prefab, overrides: B) -> EntityID {
+        let eid = prefab.spawn(self);
+        self.insert_bundle(eid, overrides);
+        return eid;
+    }
+
+    /// Places a component of a bundle once the entity is in its final archetype
+    pub(crate) fn write_bundled<T: Component>(&mut self, eid: EntityID, value: T, replace: bool) {
+        let location = self.archetypes.location(eid).unwrap();
+        if replace {
+            self.get_store_mut::<T>().replace(location, value);
+            self.mutations.record(Trigger::Mutate(T::ID), eid);
+        } else {
+            let row = self.get_store_mut::<T>().insert(location.archetype, value);
+            debug_assert_eq!(row, location.row, "Bundle out of sync with its archetype");
+            self.mutations.record(Trigger::Attach(T::ID), eid);
+        }
+    }
+
+    // // Untracked Mutations // //
+    // These don't update the queries, the caller is responsible for catching them up
+
+    /// Detaches from the parent and despawns the whole subtree, catching up touched entities before they're despawned
+    fn despawn_recursive(&mut self, touched: &mut HashMap<EntityID, Vec<ComponentID>>, eid: EntityID) -> bool {
+        if !self.is_alive(eid) { return false; }
+        self.remove_parent_untracked(touched, eid);
+
+        let entities = self.descendants(eid).collect::<Vec<_>>();
+        for eid in std::iter::once(eid).chain(entities) {
+            if let Some(before) = touched.remove(&eid) { self.catch_up_queries(eid, &before); }
+            let location = self.archetypes.location(eid).unwrap();
+            for id in self.archetypes.get(location.archetype).components().to_vec() {
+                self.update_presence(eid, id, false);
+            }
+            self.despawn_untracked(eid);
+        }
+        return true;
+    }
+
+    fn despawn_untracked(&mut self, eid: EntityID) -> bool {
+        if !self.entities.release(eid) { return false; }
+
+        let location = self.archetypes.remove(eid).expect("Live entity missing from archetypes");
+        for id in self.archetypes.get(location.archetype).components() {
+            self.component_stores.get_mut(id).unwrap().remove_row(location);
+        }
+        for store in self.relation_stores.values_mut() {
+            store.remove_entity(eid);
+        }
+        self.subscriptions.release(eid);
+        self.mutations.record(Trigger::Despawn, eid);
+        return true;
+    }
+
+    /// Removes an entity as if it were despawned, handing back its components
+    fn take_untracked(&mut self, eid: EntityID) -> Vec<(ComponentID, Box<dyn Any>)> {
+        self.entities.release(eid);
+
+        let location = self.archetypes.remove(eid).expect("Live entity missing from archetypes");
+        let mut result = Vec::new();
+        for id in self.archetypes.get(location.archetype).components() {
+            result.push((*id, self.component_stores.get_mut(id).unwrap().take_boxed(location)));
+        }
+        for store in self.relation_stores.values_mut() {
+            store.remove_entity(eid);
+        }
+        self.subscriptions.release(eid);
+        self.mutations.record(Trigger::Despawn, eid);
+        return result;
+    }
+
+    fn attach_untracked<T: Component>(&mut self, eid: EntityID, value: T) -> Option<T> {
+        let source = self.archetypes.location(eid).expect("Attempt to attach a component to a dead entity");
+        if self.archetypes.get(source.archetype).contains(T::ID) {
+            self.mutations.record(Trigger::Mutate(T::ID), eid);
+            return Some(self.get_store_mut::<T>().replace(source, value));
+        }
+
+        let target = self.archetypes.with_component(source.archetype, T::ID);
+        self.relocate(eid, target);
+        self.get_store_mut::<T>().insert(target, value);
+        self.mutations.record(Trigger::Attach(T::ID), eid);
+        return None;
+    }
+
+    fn detach_untracked<T: Component>(&mut self, eid: EntityID) -> Option<T> {
+        let source = self.archetypes.location(eid)?;
+        if !self.archetypes.get(source.archetype).contains(T::ID) { return None; }
+
+        let target = self.archetypes.without_component(source.archetype, T::ID);
+        let value = self.get_store_mut::<T>().remove(source);
+        self.relocate(eid, target);
+        self.mutations.record(Trigger::Detach(T::ID), eid);
+        return Some(value);
+    }
+
+    fn attach_boxed_untracked(&mut self, eid: EntityID, id: ComponentID, value: Box<dyn Any>) -> bool {
+        let source = self.archetypes.location(eid).expect("Attempt to attach a component to a dead entity");
+        let store = self.component_stores.get_mut(&id).unwrap_or_else(|| panic!("ComponentStore not registered for ComponentID({})", id.0));
+        if self.archetypes.get(source.archetype).contains(id) {
+            store.replace_boxed(source, value);
+            self.mutations.record(Trigger::Mutate(id), eid);
+            return false;
+        }
+
+        let target = self.archetypes.with_component(source.archetype, id);
+        self.relocate(eid, target);
+        self.component_stores.get_mut(&id).unwrap().insert_boxed(target, value);
+        self.mutations.record(Trigger::Attach(id), eid);
+        return true;
+    }
+
+    /// Returns false if either entity is dead or the parent is the child's own descendant
+    fn set_parent_untracked(&mut self, touched: &mut HashMap<EntityID, Vec<ComponentID>>, child: EntityID, parent: EntityID) -> bool {
+        if !self.is_alive(child) || !self.is_alive(parent) { return false; }
+        if child == parent || self.ancestors(parent).any(|v| v == child) { return false; }
+        if self.parent(child) == Some(parent) { return true; }
+
+        self.remove_parent_untracked(touched, child);
+        self.touch(touched, child);
+        self.touch(touched, parent);
+        self.attach_untracked(child, Parent(parent));
+        match self.get_mut::<Children>(parent) {
+            Some(v) => v.0.push(child),
+            None    => { self.attach_untracked(parent, Children(vec![child])); },
+        }
+        return true;
+    }
+
+    fn remove_parent_untracked(&mut self, touched: &mut HashMap<EntityID, Vec<ComponentID>>, child: EntityID) -> Option<EntityID> {
+        let parent = self.parent(child)?;
+        self.touch(touched, child);
+        self.touch(touched, parent);
+        self.detach_untracked::<Parent>(child);
+
+        let children = self.get_mut::<Children>(parent).expect("Parent missing its Children");
+        children.0.retain(|v| *v != child);
+        if children.0.is_empty() { self.detach_untracked::<Children>(parent); }
+        return Some(parent);
+    }
+
+    fn detach_id_untracked(&mut self, eid: EntityID, id: ComponentID) -> bool {
+        let source = match self.archetypes.location(eid) {
+            Some(v) => v,
+            None    => return false,
+        };
+        if !self.archetypes.get(source.archetype).contains(id) { return false; }
+
+        let target = self.archetypes.without_component(source.archetype, id);
+        self.component_stores.get_mut(&id).unwrap().remove_row(source);
+        self.relocate(eid, target);
+        self.mutations.record(Trigger::Detach(id), eid);
+        return true;
+    }
+
+    /// Moves an entity, and the components both archetypes share, to another archetype
+    fn relocate(&mut self, eid: EntityID, target: ArchetypeID) {
+        let (source, _) = self.archetypes.relocate(eid, target).unwrap();
+        let target = self.archetypes.get(target);
+        for id in self.archetypes.get(source.archetype).components() {
+            if !target.contains(*id) { continue; }
+            self.component_stores.get_mut(id).unwrap().move_row(source, target.id());
+        }
+    }
+
+    fn update_presence(&mut self, eid: EntityID, id: ComponentID, attach: bool) {
+        for (query, index) in self.component_stores[&id].get_queries() {
+            self.queries.update_presence(eid, query, *index, attach);
+        }
+    }
+
+    // // Commands // //
+
+    /// Applies a command buffer in the order it was recorded, returning the entities it spawned.
+    /// Queries are caught up once per entity at the end of the batch, so a component that's attached
+    /// and detached again within the batch never touches them. Panics on spawned targets taken from another buffer.
+    pub fn apply(&mut self, commands: Commands) -> Vec<EntityID> {
+        let mut spawned = Vec::new();
+        let mut touched: HashMap<EntityID, Vec<ComponentID>> = Default::default(); // Components before the batch
+
+        for command in commands.into_inner() {
+            let resolve = |target: CommandTarget, spawned: &Vec<EntityID>| match target {
+                CommandTarget::Entity(v)  => v,
+                CommandTarget::Spawned(v) => *spawned.get(v.0 as usize).expect("Spawned target out of range, it was taken from another command buffer"),
+            };
+
+            match command {
+                Command::Spawn => {
+                    let eid = self.spawn();
+                    touched.insert(eid, Vec::new());
+                    spawned.push(eid);
+                },
+                Command::Despawn(target) => {
+                    // Caught up as they're despawned, the slots may be reused within the batch
+                    let eid = resolve(target, &spawned);
+                    self.despawn_recursive(&mut touched, eid);
+                },
+                Command::Attach(target, id, value) => {
+                    let eid = resolve(target, &spawned);
+                    if !self.is_alive(eid) { continue; }
+                    self.touch(&mut touched, eid);
+                    self.attach_boxed_untracked(eid, id, value);
+                },
+                Command::Detach(target, id) => {
+                    let eid = resolve(target, &spawned);
+                    if !self.is_alive(eid) { continue; }
+                    self.touch(&mut touched, eid);
+                    self.detach_id_untracked(eid, id);
+                },
+                Command::Set(target, id, value) => {
+                    let eid = resolve(target, &spawned);
+                    if !self.has_id(eid, id) { continue; }
+                    self.attach_boxed_untracked(eid, id, value);
+                },
+                Command::Send(targets, id, value) => {
+                    let entities = targets.into_iter().map(|v| resolve(v, &spawned)).collect();
+                    self.send_boxed(entities, id, value);
+                },
+                Command::InsertResource(value) => { self.resources.insert_boxed(value); },
+                Command::RemoveResource(id)    => { self.resources.remove_id(id); },
+                Command::SetParent(child, parent) => {
+                    let (child, parent) = (resolve(child, &spawned), resolve(parent, &spawned));
+                    self.set_parent_untracked(&mut touched, child, parent);
+                },
+                Command::RemoveParent(child) => {
+                    let child = resolve(child, &spawned);
+                    self.remove_parent_untracked(&mut touched, child);
+                },
+                Command::Relate(source, target, id, value) => {
+                    let (source, target) = (resolve(source, &spawned), resolve(target, &spawned));
+                    if !self.is_alive(source) || !self.is_alive(target) { continue; }
+                    self.relation_store_any(id).insert_boxed(source, target, value);
+                },
+                Command::Unrelate(source, target, id) => {
+                    let (source, target) = (resolve(source, &spawned), resolve(target, &spawned));
+                    self.relation_store_any(id).remove_pair(source, target);
+                },
+            }
+        }
+
+        self.catch_up_touched(touched);
+        return spawned;
+    }
+
+    fn touch(&self, touched: &mut HashMap<EntityID, Vec<ComponentID>>, eid: EntityID) {
+        if touched.contains_key(&eid) { return; }
+        let location = self.archetypes.location(eid).unwrap();
+        touched.insert(eid, self.archetypes.get(location.archetype).components().to_vec());
+    }
+
+    fn catch_up_touched(&mut self, touched: HashMap<EntityID, Vec<ComponentID>>) {
+        let mut touched = touched.into_iter().collect::<Vec<_>>();
+        touched.sort_unstable_by_key(|v| v.0);
+        for (eid, before) in touched {
+            self.catch_up_queries(eid, &before);
+        }
+    }
+
+    /// Updates the queries with the difference between the components an entity had and has now
+    fn catch_up_queries(&mut self, eid: EntityID, before: &[ComponentID]) {
+        let after = match self.archetypes.location(eid) {
+            Some(v) => self.archetypes.get(v.archetype).components().to_vec(),
+            None    => Vec::new(),
+        };
+        for id in before.iter().filter(|v| !after.contains(v)) {
+            self.update_presence(eid, *id, false);
+        }
+        for id in after.iter().filter(|v| !before.contains(v)) {
+            self.update_presence(eid, *id, true);
+        }
+    }
+
+    // // Queries // //
+
+    /// Registers a request so it can be queried, entities are tracked from then on as components are attached and detached
+    pub fn register_query<'a, T: ComponentRequestTupleDefinition<'a>>(&mut self) -> QueryID {
+        self.register_query_id(T::query_id())
+    }
+
+    /// Registers a mutable request, mutable and immutable requests for the same components share a query
+    pub fn register_query_mut<'a, T: ComponentMutRequestTupleDefinition<'a>>(&mut self) -> QueryID {
+        self.register_query_id(T::query_id())
+    }
+
+    fn register_query_id(&mut self, id: QueryID) -> QueryID {
+        if self.queries.contains(&id) { return id; }
+        self.queries.register(id.clone(), &mut self.component_stores);
+
+        // Catch up with entities that existed before the query, masks count partial matches too
+        for archetype in self.archetypes.iter() {
+            for (index, term) in id.terms().iter().enumerate() {
+                if !archetype.contains(term.component_id()) { continue; }
+                for eid in archetype.entities() {
+                    self.queries.update_presence(*eid, &id, index as u8, true);
+                }
+            }
+        }
+
+        return id;
+    }
+
+    /// Iterates every entity matching a registered request, ie. `ecs.query::<((A, B), (C,), (Without<D>,))>()`.
+    /// Added and Changed filters match changes made since the reacting system last ran, see last_run.
+    pub fn query<'a, T: ComponentRequestTupleDefinition<'a> + 'a>(&'a self) -> impl Iterator<Item = (EntityID, T::ReqRefComponentTuple, T::OptRefComponentTuple)> + 'a {
+        self.query_since::<T>(self.last_run)
+    }
+
+    /// Iterates a registered request, Added and Changed filters only match changes made after the given tick
+    pub fn query_since<'a, T: ComponentRequestTupleDefinition<'a> + 'a>(&'a self, since: Tick) -> impl Iterator<Item = (EntityID, T::ReqRefComponentTuple, T::OptRefComponentTuple)> + 'a {
+        let id = T::query_id();
+        assert!(self.queries.contains(&id), "Query not registered for \"{}\"", std::any::type_name::<T>());
+        let filter = T::Filter::state(self);
+        let matches = move |eid: &EntityID| {
+            let location = self.archetypes.location(*eid).expect("Query out of sync with archetypes");
+            unsafe { T::Filter::matches(filter, location, since) }
+        };
+        self.queries.iter(&id).filter(matches).map(move |eid| (
+            eid,
+            T::ReqRefComponentTuple::retrieve(self, eid).expect("Query out of sync with component stores"),
+            T::OptRefComponentTuple::retrieve(self, eid),
+        ))
+    }
+
+    /// Iterates every entity matching a registered request with mutable access, ie. `ecs.query_mut::<((A, Mut<B>), (Mut<C>,))>()`.
+    /// Panics if a mutably borrowed component is named more than once. Filters match as they do for query.
+    pub fn query_mut<'a, T: ComponentMutRequestTupleDefinition<'a> + 'a>(&'a mut self) -> impl Iterator<Item = (EntityID, T::ReqMutComponentTuple, T::OptMutComponentTuple)> + 'a {
+        self.query_mut_since::<T>(self.last_run)
+    }
+
+    /// Iterates a registered request with mutable access, Added and Changed filters only match changes made after the given tick.
+    /// Mutably borrowed components are marked as changed.
+    pub fn query_mut_since<'a, T: ComponentMutRequestTupleDefinition<'a> + 'a>(&'a mut self, since: Tick) -> impl Iterator<Item = (EntityID, T::ReqMutComponentTuple, T::OptMutComponentTuple)> + 'a {
+        // Only the mutation log is borrowed mutably while the components are, the fields never overlap
+        let accesses = T::accesses();
+        let iter = unsafe { Self::query_mut_split::<T>(self, &self.queries, &self.archetypes, since) };
+        let archetypes = &self.archetypes;
+        let mutations = &mut self.mutations;
+        iter.map(move |v| {
+            Self::record_mutations(mutations, archetypes, v.0, &accesses);
+            v
+        })
+    }
+
+    /// Iterates a registered request with mutable access, without recording any mutations.
+    /// Caller must ensure that nothing else borrows the mutably requested components while iterating.
+    pub(crate) unsafe fn query_mut_raw<'a, T: ComponentMutRequestTupleDefinition<'a> + 'a>(&'a self, since: Tick) -> impl Iterator<Item = (EntityID, T::ReqMutComponentTuple, T::OptMutComponentTuple)> + 'a {
+        Self::query_mut_split::<T>(self, &self.queries, &self.archetypes, since)
+    }
+
+    /// Takes the store pointers from the ECS up front, so iterating only borrows the queries and archetypes.
+    /// Caller must ensure that nothing else borrows the mutably requested components while iterating.
+    unsafe fn query_mut_split<'a, T: ComponentMutRequestTupleDefinition<'a> + 'a>(&self, queries: &'a QueryContainer, archetypes: &'a Archetypes, since: Tick) -> impl Iterator<Item = (EntityID, T::ReqMutComponentTuple, T::OptMutComponentTuple)> + 'a {
+        assert_no_aliasing(&T::accesses());
+        let id = T::query_id();
+        assert!(queries.contains(&id), "Query not registered for \"{}\"", std::any::type_name::<T>());
+
+        // Each entity is yielded once and no component is borrowed twice, so the borrows never alias
+        let required = T::ReqMutComponentTuple::state(self);
+        let optional = T::OptMutComponentTuple::state(self);
+        let filter = T::Filter::state(self);
+        queries.iter(&id).filter_map(move |eid| unsafe {
+            let location = archetypes.location(eid).expect("Query out of sync with archetypes");
+            match T::Filter::matches(filter, location, since) {
+                true  => Some((
+                    eid,
+                    T::ReqMutComponentTuple::retrieve(required, location).expect("Query out of sync with component stores"),
+                    T::OptMutComponentTuple::retrieve(optional, location),
+                )),
+                false => None,
+            }
+        })
+    }
+
+    pub(crate) fn record_mutation(&mut self, trigger: Trigger, eid: EntityID) {
+        self.mutations.record(trigger, eid);
+    }
+
+    /// Records a Mutate for every mutably borrowed component the entity has
+    fn record_mutations(mutations: &mut MutationLog, archetypes: &Archetypes, eid: EntityID, accesses: &[ComponentAccess]) {
+        let archetype = match archetypes.location(eid) {
+            Some(v) => archetypes.get(v.archetype),
+            None    => return,
+        };
+        for access in accesses.iter().filter(|v| v.mutable && archetype.contains(v.id)) {
+            mutations.record(Trigger::Mutate(access.id), eid);
+        }
+    }
+
+    // // Accessors // //
+
+    pub fn archetypes(&self) -> &Archetypes {
+        &self.archetypes
+    }
+
+    pub fn has<T: Component>(&self, eid: EntityID) -> bool {
+        self.has_id(eid, T::ID)
+    }
+
+    pub fn has_id(&self, eid: EntityID, id: ComponentID) -> bool {
+        match self.archetypes.location(eid) {
+            Some(location) => self.archetypes.get(location.archetype).contains(id),
+            None           => false,
+        }
+    }
+
+    pub fn get_ref<T: Component>(&self, eid: EntityID) -> Option<&T> {
+        let location = self.archetypes.location(eid)?;
+        self.get_store_ref::<T>().get_ref(location)
+    }
+
+    /// Mutably borrows a component, marking it as changed
+    pub fn get_mut<T: Component>(&mut self, eid: EntityID) -> Option<&mut T> {
+        let location = self.archetypes.location(eid)?;
+        if self.archetypes.get(location.archetype).contains(T::ID) {
+            self.mutations.record(Trigger::Mutate(T::ID), eid);
+        }
+        self.get_store_mut::<T>().get_mut(location)
+    }
+
+    pub fn get_ticks<T: Component>(&self, eid: EntityID) -> Option<ComponentTicks> {
+        let location = self.archetypes.location(eid)?;
+        self.get_store_ref::<T>().get_ticks(location)
+    }
+
+    /// The address of a component by ID, for components that aren't known at compile time
+    pub fn get_ptr(&self, eid: EntityID, id: ComponentID) -> Option<*const u8> {
+        let location = self.archetypes.location(eid)?;
+        self.component_stores.get(&id)?.get_ptr(location)
+    }
+
+    /// The address of a component by ID, marking it as changed
+    pub fn get_mut_ptr(&mut self, eid: EntityID, id: ComponentID) -> Option<*mut u8> {
+        let location = self.archetypes.location(eid)?;
+        let result = self.component_stores.get_mut(&id)?.get_mut_ptr(location)?;
+        self.mutations.record(Trigger::Mutate(id), eid);
+        return Some(result);
+    }
+
+    // // Change Detection // //
+
+    /// The tick stamped onto components as they're attached or mutably accessed
+    pub fn change_tick(&self) -> Tick {
+        self.change_tick
+    }
+
+    /// The tick the reacting system last ran in, which query and query_mut filter changes by.
+    /// The default tick outside of dispatch, so every change matches.
+    pub fn last_run(&self) -> Tick {
+        self.last_run
+    }
+
+    /// Moves on to the next tick, returning the tick that just finished.
+    /// Querying since the returned tick only sees changes made after this call, events sent two ticks ago are dropped.
+    pub fn advance_tick(&mut self) -> Tick {
+        let result = self.next_change_tick();
+        for channel in self.event_channels.values_mut() {
+            channel.update();
+        }
+        return result;
+    }
+
+    /// Moves the change tick on without touching events, returning the previous tick. Done every time a system has
+    /// run so the changes made after it are newer than its last run, even within a single frame.
+    pub(crate) fn next_change_tick(&mut self) -> Tick {
+        let result = self.change_tick;
+        self.change_tick = Tick(result.0 + 1);
+        for store in self.component_stores.values_mut() {
+            store.set_change_tick(self.change_tick);
+        }
+        return result;
+    }
+
+    // // Events // //
+
+    pub fn register_event<T: Event>(&mut self) {
+        let result = self.event_channels.insert(T::ID, box Events::<T>::new());
+        assert!(result.is_none(), "EventID({}) conflict between \"{}\" and \"{}\"", T::ID.0, T::ID_STR, result.unwrap().event_id_str());
+    }
+
+    pub fn events<T: Event>(&self) -> &Events<T> { unsafe {
+        let channel = self.event_channels
+            .get(&T::ID)
+            .expect(&format!("Event not registered for \"{}\"", std::any::type_name::<T>()));
+        downcast_ref_unchecked::<Events<T>>(channel.as_any())
+    }}
+
+    fn events_mut<T: Event>(&mut self) -> &mut Events<T> { unsafe {
+        let channel = self.event_channels
+            .get_mut(&T::ID)
+            .expect(&format!("Event not registered for \"{}\"", std::any::type_name::<T>()));
+        downcast_mut_unchecked::<Events<T>>(channel.as_any_mut())
+    }}
+
+    /// Sends an event, triggering systems for each of the given entities. Returns the event's sequence number
+    pub fn send<T: Event>(&mut self, entities: &[EntityID], data: T) -> u64 {
+        for eid in entities {
+            self.mutations.record(Trigger::Event(T::ID), *eid);
+        }
+        self.events_mut::<T>().send(entities.to_vec(), data)
+    }
+
+    /// Sends a boxed event by ID, triggering systems for each of the given entities
+    pub fn send_boxed(&mut self, entities: Vec<EntityID>, id: EventID, value: Box<dyn Any + Send>) -> u64 {
+        for eid in &entities {
+            self.mutations.record(Trigger::Event(id), *eid);
+        }
+        let channel = self.event_channels.get_mut(&id).unwrap_or_else(|| panic!("Event not registered for EventID({})", id.0));
+        channel.send_boxed(entities, value)
+    }
+
+    /// A reader that only sees events sent from now on
+    pub fn event_reader<T: Event>(&self) -> EventReader<T> {
+        self.events::<T>().reader()
+    }
+
+    /// Reads every buffered event the reader hasn't seen yet
+    pub fn read<'a, T: Event>(&'a self, reader: &mut EventReader<T>) -> impl Iterator<Item = &'a EventRecord<T>> {
+        self.events::<T>().read(reader)
+    }
+
+    // // Reactive Systems // //
+
+    pub fn register_system<S: ReactiveSystem>(&mut self, system: S) -> SystemID {
+        let id = self.systems.register(system);
+        for trigger in self.systems.triggers(id) {
+            self.mutations.watch(*trigger);
+        }
+        return id;
+    }
+
+    pub fn systems(&self) -> &Systems {
+        &self.systems
+    }
+
+    /// Runs every system triggered by the mutations made since the last dispatch, in registration order.
+    /// Each system's commands are applied once all of its invocations have run, mutations they make are
+    /// picked up by the next dispatch. Returns the number of invocations.
+    pub fn dispatch(&mut self) -> usize {
+        self.dispatch_traced(&mut Vec::new())
+    }
+
+    /// Dispatches until systems stop triggering each other, returning the total number of invocations.
+    /// Fails once the cascade is deeper than the maximum depth, discarding the mutations that were left so
+    /// a loop doesn't carry on into the next call.
+    pub fn cascade(&mut self) -> Result<usize, CascadeError> {
+        let mut edges = Vec::new();
+        let mut invocations = 0;
+        for _ in 0..self.max_cascade_depth {
+            if self.mutations.is_empty() { return Ok(invocations); }
+            invocations += self.dispatch_traced(&mut edges);
+        }
+        if self.mutations.is_empty() { return Ok(invocations); }
+
+        // Still going, work out which systems are responsible. The Despawns of pending subscriptions go with the mutations.
+        let mutations = self.mutations.take();
+        self.subscriptions.clear_despawned();
+        let mut reacting = self.systems.ids().filter(|v| self.systems.triggered_by(*v, &mutations).next().is_some()).collect::<Vec<_>>();
+        let subscribed = |v: &Mutation| self.subscriptions.get(v.eid).iter().any(|s| Some(s.id()) != v.subscription && s.triggers().contains(&v.trigger));
+        if mutations.iter().any(subscribed) { reacting.push(SystemID::SUBSCRIPTIONS); }
+        let path = find_cascade_path(&edges, &reacting);
+        let description = match path.is_empty() {
+            true  => reacting.iter().map(|v| self.systems.name(*v)).collect::<Vec<_>>().join(", "),
+            false => {
+                let mut result = self.systems.name(path[0].source).to_string();
+                for edge in &path {
+                    result += &format!(" -[{}]-> {}", self.trigger_name(edge.trigger), self.systems.name(edge.target));
+                }
+                result
+            }
+        };
+        return Err(CascadeError{ depth: self.max_cascade_depth, path, description });
+    }
+
+    /// The maximum number of dispatches a cascade can take before it's treated as a reaction loop
+    pub fn max_cascade_depth(&self) -> usize {
+        self.max_cascade_depth
+    }
+
+    pub fn set_max_cascade_depth(&mut self, depth: usize) {
+        self.max_cascade_depth = depth;
+    }
+
+    /// Readable trigger, naming the component or event by its ID_STR
+    pub fn trigger_name(&self, trigger: Trigger) -> String {
+        let component = |id: ComponentID| self.component_stores.get(&id).map_or_else(|| format!("ComponentID({})", id.0), |v| v.component_id_str().to_string());
+        match trigger {
+            Trigger::Spawn      => "Spawn".to_string(),
+            Trigger::Despawn    => "Despawn".to_string(),
+            Trigger::Attach(id) => format!("Attach({})", component(id)),
+            Trigger::Detach(id) => format!("Detach({})", component(id)),
+            Trigger::Mutate(id) => format!("Mutate({})", component(id)),
+            Trigger::Event(id)  => format!("Event({})", self.event_channels.get(&id).map_or_else(|| format!("EventID({})", id.0), |v| v.event_id_str().to_string())),
+        }
+    }
+
+    /// Dispatches once, noting which systems triggered which
+    fn dispatch_traced(&mut self, edges: &mut Vec<CascadeEdge>) -> usize {
+        let mutations = self.mutations.take();
+        let mut invocations = 0;
+
+        for id in self.systems.ids().collect::<Vec<_>>() {
+            for mutation in self.systems.triggered_by(id, &mutations) {
+                let source = match mutation.source { Some(v) => v, None => continue };
+                let edge = CascadeEdge{ source, trigger: mutation.trigger, target: id };
+                if !edges.contains(&edge) { edges.push(edge); }
+            }
+
+            let entities = self.systems.triggered(id, &mutations);
+            if entities.is_empty() { continue; }
+
+            let mut system = self.systems.take(id);
+            let mut commands = Commands::new();
+            self.last_run = self.systems.last_run(id);
+            for eid in entities {
+                system.react(self, eid, &mut commands);
+                invocations += 1;
+            }
+            self.last_run = Tick::default();
+            self.systems.restore(id, system);
+            let run = self.next_change_tick();
+            self.systems.set_last_run(id, run);
+
+            // Finalize, the commands are newer than the run
+            self.mutations.set_source(Some(id));
+            self.apply(commands);
+            self.mutations.set_source(None);
+        }
+
+        return invocations + self.dispatch_subscriptions(&mutations, edges);
+    }
+
+    /// Invokes the subscriptions of the mutated entities, finalizing them once every callback has run. A subscription
+    /// isn't triggered by its own output, anything else it causes is dispatched next like the output of a system.
+    fn dispatch_subscriptions(&mut self, mutations: &[Mutation], edges: &mut Vec<CascadeEdge>) -> usize {
+        let mut triggered = mutations.iter()
+            .filter(|v| self.subscriptions.contains(v.eid))
+            .collect::<Vec<_>>();
+        if triggered.is_empty() { return 0; }
+        triggered.sort_unstable_by_key(|v| (v.eid, v.trigger, v.subscription));
+        triggered.dedup_by_key(|v| (v.eid, v.trigger, v.subscription));
+
+        let mut outputs = Vec::new();
+        let mut invocations = 0;
+        let mut start = 0;
+        while start < triggered.len() {
+            // Sorted, so each entity's mutations are contiguous
+            let eid = triggered[start].eid;
+            let end = start + triggered[start..].iter().take_while(|v| v.eid == eid).count();
+            let mut subscriptions = self.subscriptions.take(eid);
+            for subscription in &mut subscriptions {
+                let mut commands = Commands::new();
+                let id = subscription.id();
+                let mut last = None;
+                let matched = triggered[start..end].iter().filter(|v| v.subscription != Some(id) && subscription.triggers().contains(&v.trigger)).collect::<Vec<_>>();
+                for mutation in matched {
+                    if let Some(source) = mutation.source {
+                        let edge = CascadeEdge{ source, trigger: mutation.trigger, target: SystemID::SUBSCRIPTIONS };
+                        if !edges.contains(&edge) { edges.push(edge); }
+                    }
+                    // Once per trigger, no matter how many sources made it
+                    if last == Some(mutation.trigger) { continue; }
+                    last = Some(mutation.trigger);
+                    subscription.invoke(self, eid, mutation.trigger, &mut commands);
+                    invocations += 1;
+                }
+                outputs.push((id, commands));
+            }
+            if self.is_alive(eid) { self.subscriptions.restore(eid, subscriptions); }
+            start = end;
+        }
+
+        // Finalize, each subscription's output is tagged so it only skips itself
+        for (id, commands) in outputs {
+            self.mutations.set_subscription(Some(id));
+            self.apply(commands);
+        }
+        self.mutations.set_subscription(None);
+        return invocations;
+    }
+
+    // // Dynamic Components // //
+
+    /// Registers a component defined at runtime, returning the ID its values are attached by
+    pub fn register_dynamic_component(&mut self, info: DynamicComponentInfo) -> ComponentID {
+        let mut store = DynamicStore::new(info);
+        store.set_change_tick(self.change_tick);
+        let result = self.component_stores.insert(info.id(), StoreCell::new(store));
+        assert!(result.is_none(), "ComponentID({}) conflict between \"{}\" and \"{}\", rename one of them", info.id().0, info.name(), result.unwrap().component_id_str());
+        return info.id();
+    }
+
+    /// The description of a dynamic component, None if the ID belongs to a typed component or isn't registered
+    pub fn dynamic_info(&self, id: ComponentID) -> Option<&DynamicComponentInfo> {
+        self.dynamic_store(id).map(|v| v.info())
+    }
+
+    pub fn dynamic_store(&self, id: ComponentID) -> Option<&DynamicStore> {
+        self.component_stores.get(&id)?.as_any().downcast_ref::<DynamicStore>()
+    }
+
+    /// Attaches a dynamic component, returning false if the entity already had one and it was replaced instead
+    pub fn attach_dynamic(&mut self, eid: EntityID, value: DynamicValue) -> bool {
+        self.attach_boxed(eid, value.id(), box value)
+    }
+
+    /// A copy of a dynamic component, None if the entity doesn't have it or it can't be cloned
+    pub fn clone_dynamic(&self, eid: EntityID, id: ComponentID) -> Option<DynamicValue> {
+        let location = self.archetypes.location(eid)?;
+        self.dynamic_store(id)?.clone_value(location)
+    }
+
+    // // Resources // //
+
+    /// Inserts a singleton, returning the previous value if there was one
+    pub fn insert_resource<R: Resource>(&mut self, value: R) -> Option<R> {
+        self.resources.insert(value)
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        self.resources.remove::<R>()
+    }
+
+    pub fn has_resource<R: Resource>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+
+    pub fn resource<R: Resource>(&self) -> Option<&R> {
+        self.resources.get::<R>()
+    }
+
+    pub fn resource_mut<R: Resource>(&mut self) -> Option<&mut R> {
+        self.resources.get_mut::<R>()
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Caller must ensure that the resource isn't borrowed elsewhere while the pointer is dereferenced mutably
+    pub(crate) unsafe fn resource_ptr<R: Resource>(&self) -> Option<*mut R> {
+        self.resources.get_ptr::<R>()
+    }
+
+    // // Hierarchy // //
+
+    /// Moves an entity under another, detaching it from its previous parent.
+    /// Panics if either entity is dead or the parent is the child's own descendant.
+    pub fn set_parent(&mut self, child: EntityID, parent: EntityID) {
+        assert!(self.is_alive(child) && self.is_alive(parent), "Attempt to parent a dead entity");
+        let mut touched = HashMap::default();
+        let result = self.set_parent_untracked(&mut touched, child, parent);
+        self.catch_up_touched(touched);
+        assert!(result, "Attempt to parent an entity to itself or one of its descendants");
+    }
+
+    /// Detaches an entity from its parent, returning the parent if it had one
+    pub fn remove_parent(&mut self, child: EntityID) -> Option<EntityID> {
+        let mut touched = HashMap::default();
+        let result = self.remove_parent_untracked(&mut touched, child);
+        self.catch_up_touched(touched);
+        return result;
+    }
+
+    pub fn parent(&self, eid: EntityID) -> Option<EntityID> {
+        self.get_ref::<Parent>(eid).map(|v| v.0)
+    }
+
+    pub fn children(&self, eid: EntityID) -> &[EntityID] {
+        self.get_ref::<Children>(eid).map_or(&[], |v| v.as_slice())
+    }
+
+    /// The parent, grandparent and so on up to the root
+    pub fn ancestors(&self, eid: EntityID) -> impl Iterator<Item = EntityID> + '_ {
+        std::iter::successors(self.parent(eid), move |v| self.parent(*v))
+    }
+
+    /// Every entity below this one, depth-first
+    pub fn descendants(&self, eid: EntityID) -> impl Iterator<Item = EntityID> + '_ {
+        self.traverse(eid).skip(1).map(|v| v.0)
+    }
+
+    /// The entity followed by every entity below it, depth-first, paired with their depth relative to the entity.
+    /// Empty if the entity is dead.
+    pub fn traverse(&self, root: EntityID) -> impl Iterator<Item = (EntityID, usize)> + '_ {
+        let mut stack = match self.is_alive(root) {
+            true  => vec![(root, 0)],
+            false => Vec::new(),
+        };
+        std::iter::from_fn(move || {
+            let (eid, depth) = stack.pop()?;
+            stack.extend(self.children(eid).iter().rev().map(|v| (*v, depth + 1)));
+            Some((eid, depth))
+        })
+    }
+
+    // // Relations // //
+
+    pub fn register_relation<R: Relation>(&mut self, chunk_size: ChunkSize) {
+        let result = self.relation_stores.insert(R::ID, box RelationStore::<R>::new(chunk_size));
+        assert!(result.is_none(), "RelationID({}) conflict between \"{}\" and \"{}\"", R::ID.0, R::ID_STR, result.unwrap().relation_id_str());
+    }
+
+    pub fn relations<R: Relation>(&self) -> &RelationStore<R> { unsafe {
+        let store = self.relation_stores
+            .get(&R::ID)
+            .expect(&format!("Relation not registered for \"{}\"", std::any::type_name::<R>()));
+        downcast_ref_unchecked::<RelationStore<R>>(store.as_any())
+    }}
+
+    fn relations_mut<R: Relation>(&mut self) -> &mut RelationStore<R> { unsafe {
+        let store = self.relation_stores
+            .get_mut(&R::ID)
+            .expect(&format!("Relation not registered for \"{}\"", std::any::type_name::<R>()));
+        downcast_mut_unchecked::<RelationStore<R>>(store.as_any_mut())
     }}
 
+    fn relation_store_any(&mut self, id: RelationID) -> &mut Box<dyn RelationStoreAny> {
+        self.relation_stores.get_mut(&id).unwrap_or_else(|| panic!("Relation not registered for RelationID({})", id.0))
+    }
+
+    /// Relates a pair, returning the previous value if they were already related.
+    /// The pair is dropped once either entity is despawned.
+    pub fn relate<R: Relation>(&mut self, source: EntityID, target: EntityID, value: R) -> Option<R> {
+        assert!(self.is_alive(source) && self.is_alive(target), "Attempt to relate a dead entity");
+        self.relations_mut::<R>().insert(source, target, value)
+    }
+
+    pub fn unrelate<R: Relation>(&mut self, source: EntityID, target: EntityID) -> Option<R> {
+        self.relations_mut::<R>().remove(source, target)
+    }
+
+    pub fn is_related<R: Relation>(&self, source: EntityID, target: EntityID) -> bool {
+        self.relations::<R>().contains(source, target)
+    }
+
+    pub fn get_relation<R: Relation>(&self, source: EntityID, target: EntityID) -> Option<&R> {
+        self.relations::<R>().get(source, target)
+    }
+
+    pub fn get_relation_mut<R: Relation>(&mut self, source: EntityID, target: EntityID) -> Option<&mut R> {
+        self.relations_mut::<R>().get_mut(source, target)
+    }
+
+    /// Every entity the source is related to, ie. everything the player `Targets`
+    pub fn targets<R: Relation>(&self, source: EntityID) -> impl Iterator<Item = (EntityID, &R)> {
+        self.relations::<R>().targets(source)
+    }
+
+    /// Every entity related to the target, ie. everything that `Targets` the player
+    pub fn sources<R: Relation>(&self, target: EntityID) -> impl Iterator<Item = EntityID> + '_ {
+        self.relations::<R>().sources(target)
+    }
+
+    // // Serialization // //
+
+    /// Includes a component in saves, its store must already be registered
+    pub fn register_serializable<T: SerializeComponent>(&mut self) {
+        assert!(self.component_stores.contains_key(&T::ID), "ComponentStore not registered for \"{}\"", std::any::type_name::<T>());
+        self.serializers.register::<T>();
+    }
+
+    pub fn is_serializable(&self, id: ComponentID) -> bool {
+        self.serializers.contains(id)
+    }
+
+    /// Includes a relation in saves, it must already be registered
+    pub fn register_serializable_relation<R: SerializeRelation>(&mut self) {
+        assert!(self.relation_stores.contains_key(&R::ID), "Relation not registered for \"{}\"", std::any::type_name::<R>());
+        self.serializers.register_relation::<R>();
+    }
+
+    pub fn is_relation_serializable(&self, id: RelationID) -> bool {
+        self.serializers.contains_relation(id)
+    }
+
+    /// Saves every entity along with its serializable components and relations, other components and relations are skipped
+    pub fn save<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        WorldRef{ ecs: self, serializers: &self.serializers }.serialize(serializer)
+    }
+
+    pub fn save_binary(&self) -> Result<Vec<u8>, SerializationError> {
+        Ok(bincode::DefaultOptions::new().serialize(&WorldRef{ ecs: self, serializers: &self.serializers })?)
+    }
+
+    pub fn save_text(&self) -> Result<String, SerializationError> {
+        Ok(ron::ser::to_string_pretty(&WorldRef{ ecs: self, serializers: &self.serializers }, Default::default())?)
+    }
+
+    /// Spawns every entity of a save, returning the entities they were loaded as.
+    /// EntityIDs held by loaded components and relations are remapped, those that weren't part of the save become invalid.
+    /// Nothing is spawned if the save fails to load.
+    pub fn load<'de, D: Deserializer<'de>>(&mut self, deserializer: D) -> Result<EntityMap, D::Error> {
+        let world = WorldSeed(&self.serializers).deserialize(deserializer)?;
+        return Ok(self.insert_loaded(world));
+    }
+
+    pub fn load_binary(&mut self, bytes: &[u8]) -> Result<EntityMap, SerializationError> {
+        let world = bincode::DefaultOptions::new().deserialize_seed(WorldSeed(&self.serializers), bytes)?;
+        return Ok(self.insert_loaded(world));
+    }
+
+    pub fn load_text(&mut self, text: &str) -> Result<EntityMap, SerializationError> {
+        let mut deserializer = ron::Deserializer::from_str(text)?;
+        let world = WorldSeed(&self.serializers).deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        return Ok(self.insert_loaded(world));
+    }
+
+    /// Spawns every entity before attaching components, so references between them can be remapped.
+    /// Each entity is placed straight into the archetype of its components.
+    /// Serializable components remap themselves, others are remapped through reflection if they're registered for it.
+    /// Relations are remapped on both ends, pairs with an end that wasn't loaded are dropped.
+    fn insert_loaded(&mut self, world: LoadedWorld) -> EntityMap {
+        let LoadedWorld{ entities, relations } = world;
+        let mut map = EntityMap::default();
+        for entity in &entities {
+            map.insert(entity.eid, self.spawn());
+        }
+        for entity in entities {
+            let eid = map.get(entity.eid);
+            let mut components = entity.components;
+            components.sort_by_key(|v| v.0);
+            components.dedup_by_key(|v| v.0);
+            for (id, value) in &mut components {
+                assert!(self.component_stores.contains_key(id), "ComponentStore not registered for ComponentID({})", id.0);
+                if self.serializers.contains(*id) { self.serializers.map_entities(*id, &mut **value, &map); }
+            }
+
+            let ids = components.iter().map(|v| v.0).collect::<Vec<_>>();
+            let target = self.archetypes.get_or_insert(ids.clone());
+            self.relocate(eid, target);
+            let location = self.archetypes.location(eid).unwrap();
+            for (id, value) in components {
+                let row = self.component_stores.get_mut(&id).unwrap().insert_boxed(target, value);
+                debug_assert_eq!(row, location.row, "Loaded entity out of sync with its archetype");
+                self.mutations.record(Trigger::Attach(id), eid);
+                self.update_presence(eid, id, true);
+            }
+
+            for id in ids {
+                if self.serializers.contains(id) || !self.type_registry.contains(id) { continue; }
+                if let Some(v) = self.component_stores.get_mut(&id).unwrap().get_reflect_mut(location) { v.map_entities(&map); }
+            }
+        }
+        for mut relation in relations {
+            let (source, target) = (map.get(relation.source), map.get(relation.target));
+            if !source.is_valid() || !target.is_valid() { continue; }
+            self.serializers.map_relation(relation.id, &mut *relation.value, &map);
+            self.relation_store_any(relation.id).insert_boxed(source, target, relation.value);
+        }
+        return map;
+    }
+
+    // // Reflection // //
+
+    /// Makes a component's fields accessible by name, its store must already be registered
+    pub fn register_reflect<T: Component + Reflect>(&mut self) {
+        assert!(self.component_stores.contains_key(&T::ID), "ComponentStore not registered for \"{}\"", std::any::type_name::<T>());
+        self.type_registry.register::<T>();
+        self.get_store_mut::<T>().enable_reflect();
+    }
+
+    pub fn type_registry(&self) -> &TypeRegistry {
+        &self.type_registry
+    }
+
+    /// A component by ID as Reflect
+    pub fn reflect(&self, eid: EntityID, id: ComponentID) -> Result<&dyn Reflect, ReflectError> {
+        if !self.type_registry.contains(id) { return Err(ReflectError::NotReflected(id)); }
+        let location = self.archetypes.location(eid).ok_or(ReflectError::MissingComponent(id))?;
+        self.component_stores[&id].get_reflect(location).ok_or(ReflectError::MissingComponent(id))
+    }
+
+    /// A component by ID as Reflect, marking it as changed
+    pub fn reflect_mut(&mut self, eid: EntityID, id: ComponentID) -> Result<&mut dyn Reflect, ReflectError> {
+        if !self.type_registry.contains(id) { return Err(ReflectError::NotReflected(id)); }
+        let location = self.archetypes.location(eid).ok_or(ReflectError::MissingComponent(id))?;
+        if !self.archetypes.get(location.archetype).contains(id) { return Err(ReflectError::MissingComponent(id)); }
+        self.mutations.record(Trigger::Mutate(id), eid);
+        return Ok(self.component_stores.get_mut(&id).unwrap().get_reflect_mut(location).unwrap());
+    }
+
+    /// A field of a component by dot separated path, ie. `ecs.get_field(eid, id, "position.0")`
+    pub fn get_field(&self, eid: EntityID, id: ComponentID, path: &str) -> Result<&dyn Reflect, ReflectError> {
+        self.reflect(eid, id)?.path(path).ok_or_else(|| ReflectError::MissingField(path.into()))
+    }
+
+    /// Overwrites a field of a component by dot separated path, marking the component as changed if it succeeds
+    pub fn set_field(&mut self, eid: EntityID, id: ComponentID, path: &str, value: Box<dyn Any>) -> Result<(), ReflectError> {
+        // Resolved immutably first so a failed set doesn't record a mutation
+        let field = self.get_field(eid, id, path)?;
+        if field.as_any().type_id() != (*value).type_id() { return Err(ReflectError::WrongType(field.type_name())); }
+        return self.reflect_mut(eid, id)?.set_path(path, value);
+    }
+
+    // // Worlds // //
+
+    /// Moves entities, along with their descendants, into another world under fresh IDs. Links to parents that stay
+    /// behind are removed, as are relations to entities that stay behind, relations between moved entities are carried
+    /// over. Panics if the other world is missing a store for any of the components or relations.
+    pub fn move_entities_into(&mut self, other: &mut ECS, ids: &[EntityID]) -> EntityMap {
+        let entities = self.with_descendants(ids);
+        for eid in &entities {
+            let location = self.archetypes.location(*eid).unwrap();
+            for id in self.archetypes.get(location.archetype).components() {
+                assert!(other.component_stores.contains_key(id), "ComponentStore not registered in the destination for \"{}\"", self.component_stores[id].component_id_str());
+            }
+        }
+
+        let mut pairs = Vec::new();
+        for (id, store) in &self.relation_stores {
+            let among = store.pairs_among(&entities);
+            if among.is_empty() { continue; }
+            assert!(other.relation_stores.contains_key(id), "Relation not registered in the destination for \"{}\"", store.relation_id_str());
+            pairs.push((*id, among));
+        }
+        let mut relations = Vec::new();
+        for (id, among) in pairs {
+            let store = self.relation_store_any(id);
+            for (source, target) in among {
+                let value = store.take_boxed(source, target).unwrap();
+                relations.push(LoadedRelation{ id, source, target, value });
+            }
+        }
+
+        let entities = self.take_entities(entities);
+        return other.insert_loaded(LoadedWorld{ entities, relations });
+    }
+
+    /// Moves every entity of another world into this one under fresh IDs, see move_entities_into.
+    /// Resources, events and systems of the other world are dropped.
+    pub fn merge(&mut self, mut other: ECS) -> EntityMap {
+        let mut entities = other.archetypes.iter().flat_map(|v| v.entities().iter().copied()).collect::<Vec<_>>();
+        entities.sort_unstable();
+        return other.move_entities_into(self, &entities);
+    }
+
+    /// The live entities and all of their descendants, in order and without duplicates
+    fn with_descendants(&self, ids: &[EntityID]) -> Vec<EntityID> {
+        let mut seen = HashSet::new();
+        let mut result = Vec::new();
+        for eid in ids.iter().copied().filter(|v| self.is_alive(*v)) {
+            for eid in std::iter::once(eid).chain(self.descendants(eid)) {
+                if seen.insert(eid) { result.push(eid); }
+            }
+        }
+        return result;
+    }
+
+    /// Removes entities as if they were despawned, cutting links to parents that aren't among them
+    fn take_entities(&mut self, entities: Vec<EntityID>) -> Vec<LoadedEntity> {
+        let moved = entities.iter().copied().collect::<HashSet<_>>();
+        let mut touched = HashMap::default();
+        for eid in &entities {
+            let parent = match self.parent(*eid) {
+                Some(v) => v,
+                None    => continue,
+            };
+            if !moved.contains(&parent) { self.remove_parent_untracked(&mut touched, *eid); }
+        }
+
+        let mut result = Vec::with_capacity(entities.len());
+        for eid in entities {
+            if let Some(before) = touched.remove(&eid) { self.catch_up_queries(eid, &before); }
+            let location = self.archetypes.location(eid).unwrap();
+            for id in self.archetypes.get(location.archetype).components().to_vec() {
+                self.update_presence(eid, id, false);
+            }
+            result.push(LoadedEntity{ eid, components: self.take_untracked(eid) });
+        }
+        self.catch_up_touched(touched);
+        return result;
+    }
+
+    // // Subscriptions // //
+
+    /// Invokes a callback whenever one of the entity's mutations matches a trigger, until the entity is despawned.
+    /// Callbacks are invoked by dispatch, after the systems.
+    pub fn subscribe<F: FnMut(&ECS, EntityID, Trigger, &mut Commands) + 'static>(&mut self, eid: EntityID, triggers: Vec<Trigger>, callback: F) -> SubscriptionID {
+        assert!(self.is_alive(eid), "Attempt to subscribe to a dead entity");
+        for trigger in &triggers {
+            self.mutations.watch(*trigger);
+        }
+        self.subscriptions.insert(eid, triggers, box callback)
+    }
+
+    pub fn unsubscribe(&mut self, eid: EntityID, id: SubscriptionID) -> bool {
+        self.subscriptions.remove(eid, id)
+    }
+
+    pub fn subscriptions(&self) -> &Subscriptions {
+        &self.subscriptions
+    }
+
+    /// Collects every entity that has a component
+    pub fn entities_with<T: Component>(&self, out: &mut Vec<EntityID>) {
+        self.get_store_ref::<T>().entities(&self.archetypes, out);
+    }
+
+    /// Collects every entity that has a component by ID, in archetype order
+    pub fn entities_with_id(&self, id: ComponentID, out: &mut Vec<EntityID>) {
+        for archetype in self.archetypes.iter().filter(|v| v.contains(id)) {
+            out.extend_from_slice(archetype.entities());
+        }
+    }
+
 }
@@ -0,0 +1,137 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::{any::Any, fmt::Debug, marker::PhantomData};
+
+use crate::EntityID;
+
+#[repr(transparent)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct EventID(pub u16);
+
+/// Events are sent from the threads of the system scheduler through commands, so they must be Send
+pub trait Event: Any + Debug + Send {
+    const ID: EventID;
+    const ID_STR: &'static str;
+}
+
+pub trait EventChannelAny: Any + Debug {
+    fn event_id(&self)     -> EventID;
+    fn event_id_str(&self) -> &'static str;
+
+    fn as_any(&self)         -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Sends a boxed event, returning its sequence number. Panics if the box doesn't hold the channel's type
+    fn send_boxed(&mut self, entities: Vec<EntityID>, value: Box<dyn Any + Send>) -> u64;
+
+    /// Drops the previous tick's events, the current tick's events become the previous
+    fn update(&mut self);
+}
+
+/// An event along with the entities that triggered it
+#[derive(Debug)]
+pub struct EventRecord<T: Event> {
+    pub sequence: u64,
+    pub entities: Vec<EntityID>,
+    pub data: T,
+}
+
+/// A cursor into a channel, every consumer keeps their own so they can read at their own pace
+#[derive(Debug)]
+pub struct EventReader<T: Event> {
+    cursor: u64,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: Event> Clone for EventReader<T> {
+    fn clone(&self) -> Self {
+        Self{ cursor: self.cursor, phantom: PhantomData }
+    }
+}
+
+impl<T: Event> EventReader<T> {
+    /// The sequence number of the next event to be read
+    pub fn cursor(&self) -> u64 {
+        self.cursor
+    }
+}
+
+/// A double-buffered channel of events, events live for the tick they're sent in and the one after
+#[derive(Debug)]
+pub struct Events<T: Event> {
+    previous: Vec<EventRecord<T>>,
+    current: Vec<EventRecord<T>>,
+    sequence: u64,
+}
+
+impl<T: Event> Default for Events<T> {
+    fn default() -> Self {
+        Self{ previous: Vec::new(), current: Vec::new(), sequence: 0 }
+    }
+}
+
+impl<T: Event> EventChannelAny for Events<T> {
+    fn event_id(&self)     -> EventID      { T::ID     }
+    fn event_id_str(&self) -> &'static str { T::ID_STR }
+
+    fn as_any(&self)         -> &dyn Any     { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn send_boxed(&mut self, entities: Vec<EntityID>, value: Box<dyn Any + Send>) -> u64 {
+        match value.downcast::<T>() {
+            Ok(v)  => self.send(entities, *v),
+            Err(_) => panic!("Boxed event passed to the channel of \"{}\" has a different type", T::ID_STR),
+        }
+    }
+
+    fn update(&mut self) {
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+    }
+}
+
+impl<T: Event> Events<T> {
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sends an event, returning its sequence number
+    pub fn send(&mut self, entities: Vec<EntityID>, data: T) -> u64 {
+        self.current.push(EventRecord{ sequence: self.sequence, entities, data });
+        self.sequence += 1;
+        return self.sequence - 1;
+    }
+
+    /// A reader that only sees events sent from now on
+    pub fn reader(&self) -> EventReader<T> {
+        EventReader{ cursor: self.sequence, phantom: PhantomData }
+    }
+
+    /// A reader that also sees the events that are still buffered
+    pub fn reader_from_start(&self) -> EventReader<T> {
+        EventReader{ cursor: 0, phantom: PhantomData }
+    }
+
+    /// Reads every buffered event the reader hasn't seen yet, events that were dropped before being read are skipped
+    pub fn read<'a>(&'a self, reader: &mut EventReader<T>) -> impl Iterator<Item = &'a EventRecord<T>> {
+        let cursor = reader.cursor;
+        reader.cursor = self.sequence;
+        self.iter().skip_while(move |v| v.sequence < cursor)
+    }
+
+    /// Every buffered event, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &EventRecord<T>> {
+        self.previous.iter().chain(self.current.iter())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty() && self.current.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+}
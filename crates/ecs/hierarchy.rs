@@ -0,0 +1,72 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Component, ComponentID, EntityID, EntityMap, MapEntities, SerializeComponent};
+
+/// The entity this one belongs to. Maintained by ECS::set_parent and ECS::remove_parent, attaching or
+/// detaching it directly panics.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Parent(pub(crate) EntityID);
+
+/// The entities belonging to this one, in the order they were parented. Never empty, the component
+/// is detached along with the last child.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Children(pub(crate) Vec<EntityID>);
+
+impl Component for Parent {
+    const ID: ComponentID = ComponentID::from_name("Butterscotch_Parent");
+    const ID_STR: &'static str = "Butterscotch_Parent";
+}
+
+impl Component for Children {
+    const ID: ComponentID = ComponentID::from_name("Butterscotch_Children");
+    const ID_STR: &'static str = "Butterscotch_Children";
+}
+
+impl SerializeComponent for Parent {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.0.map_entities(map);
+    }
+}
+
+impl SerializeComponent for Children {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.0.map_entities(map);
+    }
+}
+
+impl Parent {
+    pub fn get(&self) -> EntityID {
+        self.0
+    }
+}
+
+impl Children {
+    pub fn as_slice(&self) -> &[EntityID] {
+        &self.0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = EntityID> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn contains(&self, eid: EntityID) -> bool {
+        self.0.contains(&eid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Attaching or detaching either side on its own would desync the hierarchy
+pub(crate) fn assert_not_hierarchy(id: ComponentID) {
+    assert!(id != Parent::ID && id != Children::ID, "Parent and Children can only be changed through set_parent and remove_parent");
+}
@@ -9,32 +9,87 @@
 
 use std::{convert::TryInto};
 
-use arrayvec::ArrayVec;
 use butterscotch_common::container::GID;
+use smallvec::SmallVec;
 
 mod ecs;
+mod archetype;
 
 mod component;
 mod component_tuple;
 
 mod component_store;
+mod dynamic;
 
 mod query;
+mod query_filter;
+
+mod system;
+mod commands;
+mod event;
+mod scheduler;
+mod subscription;
+mod resource;
+mod hierarchy;
+mod relation;
+mod serialization;
+mod reflect;
+mod bundle;
+
+#[cfg(test)]
+mod test;
 
 pub use ecs::*;
+pub use archetype::*;
 
 pub use component::*;
 pub use component_tuple::*;
 
 pub use component_store::*;
+pub use dynamic::*;
 
 pub use query::*;
+pub use query_filter::*;
+
+pub use system::*;
+pub use commands::*;
+pub use event::*;
+pub use scheduler::*;
+pub use subscription::*;
+pub use resource::*;
+pub use hierarchy::*;
+pub use relation::*;
+pub use serialization::*;
+pub use reflect::*;
+pub use bundle::*;
 
 #[repr(transparent)]
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
-pub struct ComponentID(pub u16);
-pub type EntityID    = GID;
-pub type QueryID     = ArrayVec<[ComponentID; 8]>;
+pub struct ComponentID(pub u32);
+
+impl ComponentID {
+    /// Hashes a namespaced name (FNV-1a), so the ID doesn't depend on build order or where the build is run.
+    /// Collisions are caught when the second component is registered.
+    pub const fn from_name(name: &str) -> ComponentID {
+        let bytes = name.as_bytes();
+        let mut hash: u32 = 0x811c9dc5;
+        let mut i = 0;
+        while i < bytes.len() {
+            hash ^= bytes[i] as u32;
+            hash = hash.wrapping_mul(0x01000193);
+            i += 1;
+        }
+        return ComponentID(hash);
+    }
+}
+
+/// Change detection counter, advanced by ECS::advance_tick and every time a system has run
+#[repr(transparent)]
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Tick(pub u32);
+
+pub type EntityID     = GID;
+pub type ComponentIDs = SmallVec<[ComponentID; 8]>;
 
 // // Passthrough TypeID Hasher // //
 #[derive(Debug, Default)]
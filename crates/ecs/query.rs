@@ -2,11 +2,66 @@
 ** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
 ** ************************************************************************ */
 
-use std::collections::{HashMap, HashSet};
+use std::{collections::HashMap, hash::BuildHasher, ops::DerefMut};
 
 use butterscotch_common::container::{ChunkSize, GIDStore};
+use smallvec::SmallVec;
 
-use crate::{ComponentID, EntityID, QueryID, ReqRefComponents, ReqRefComponentsDefinition};
+use crate::{ComponentID, EntityID, QueryFilter};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum QueryTerm {
+    With(ComponentID),
+    Without(ComponentID),
+    Or(u8, ComponentID),
+}
+
+impl QueryTerm {
+    pub fn component_id(&self) -> ComponentID {
+        match self {
+            QueryTerm::With(v)    => *v,
+            QueryTerm::Without(v) => *v,
+            QueryTerm::Or(_, v)   => *v,
+        }
+    }
+
+    /// The mask counter the term contributes to
+    fn slot(&self) -> usize {
+        match self {
+            QueryTerm::With(_)    => 0,
+            QueryTerm::Without(_) => 1,
+            QueryTerm::Or(g, _)   => 2 + (*g as usize),
+        }
+    }
+}
+
+/// The identity of a query, a sorted set of terms so the order components are requested in doesn't matter.
+/// Up to 8 terms are kept inline, larger queries spill onto the heap.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+pub struct QueryID {
+    terms: SmallVec<[QueryTerm; 8]>,
+}
+
+impl QueryID {
+    pub fn new<F: QueryFilter>(required: &[ComponentID]) -> Self {
+        let mut terms = required.iter().map(|v| QueryTerm::With(*v)).collect::<Vec<_>>();
+        F::terms(&mut terms, &mut 0);
+        terms.sort_unstable(); // Force a reliable ordering
+        terms.dedup();
+
+        assert!(terms.len() <= 256, "Query exceeds 256 terms"); // Terms are indexed by u8
+        assert!(terms.iter().any(|v| !matches!(v, QueryTerm::Without(_))), "Query requires at least one component");
+        Self{ terms: terms.into_iter().collect() }
+    }
+
+    pub fn terms(&self) -> &[QueryTerm] {
+        &self.terms
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+}
 
 #[derive(Debug, Default)]
 pub(crate) struct QueryRef {
@@ -19,101 +74,122 @@ pub struct QueryContainer {
     queries: HashMap<QueryID, QueryData>
 }
 
+/// Queries registered before their components exist would otherwise get single element chunks
+const MIN_CHUNK_SIZE: usize = 1024;
+
+/// Per-entity counters, indexed by QueryTerm::slot. Queries with more than 6 or-groups spill onto the heap.
+type QueryMask = SmallVec<[i16; 8]>;
+
 #[derive(Debug)]
 pub struct QueryData {
-    length: i8,
-    masks: GIDStore<i8>,
-    entities: HashSet<EntityID>,
+    with_count: i16,
+    slot_count: usize,
+    masks: GIDStore<QueryMask>,
+    /// Dense, so iteration order only depends on the order of mutations. Entities are appended as they start
+    /// matching, and the last entity moves into the gap when one stops.
+    entities: GIDStore<EntityID>,
 }
 
 impl QueryData {
     pub fn new(chunk_size: ChunkSize) -> Self {
         Self{
-            length: 0,
+            with_count: 0,
+            slot_count: 2,
             masks: GIDStore::new(chunk_size),
-            entities: Default::default() // TODO quick hasher function
+            entities: GIDStore::new(chunk_size),
         }
     }
+
+    /// All with terms present, no without terms present and at least one component of every or-group present
+    fn matches(&self, mask: &QueryMask) -> bool {
+        mask[0] == self.with_count && mask[1] == 0 && mask[2..].iter().all(|v| *v > 0)
+    }
 }
 
 pub trait QueryUpdater {
     fn register_query(&mut self, query: (QueryID, u8));
 
+    /// Every query this updater participates in, paired with the index of its term in the query
+    fn get_queries(&self) -> &[(QueryID, u8)];
 
     fn get_count_hint(&self) -> Option<usize>;
 }
 
 impl QueryContainer {
 
-    pub fn register<'a, T: ReqRefComponentsDefinition<'a>>(&mut self, updaters: &mut HashMap<ComponentID, Box<dyn QueryUpdater>>) -> QueryID {
-        let mut ids = T::TupleType::ids();
-        ids.sort_unstable(); // Force a reliable ordering
-        let ids = ids;
+    pub fn contains(&self, id: &QueryID) -> bool {
+        self.queries.contains_key(id)
+    }
 
+    pub fn register<U: QueryUpdater + ?Sized, B: DerefMut<Target = U>, S: BuildHasher>(&mut self, ids: QueryID, updaters: &mut HashMap<ComponentID, B, S>) -> QueryID {
         // Already have it? Skip.
         if self.queries.contains_key(&ids) { return ids; }
 
-        // Check all component types accounted for, otherwise false
-        for id in ids.iter() {
-            if !updaters.contains_key(&id) { return ids; }
+        // Check all component types accounted for
+        for term in ids.terms() {
+            assert!(updaters.contains_key(&term.component_id()), "ComponentStore not registered for ComponentID({})", term.component_id().0);
         }
 
         let mut size_hint: Option<usize> = None;
 
         // Register query with all component types
-        for i in 0..ids.len() {
-            let query_updater = updaters.get_mut(&ids[i]).unwrap();
+        for (i, term) in ids.terms().iter().enumerate() {
+            let query_updater = updaters.get_mut(&term.component_id()).unwrap();
             query_updater.register_query((ids.clone(), i as u8));
+            if let QueryTerm::Without(_) = term { continue; }
             match query_updater.get_count_hint() {
                 Some(v) => size_hint = Some(size_hint.unwrap_or(0).max(v)),
                 None    => {},
             }
         }
 
-        // Create query
-        let mut data = QueryData::new(ChunkSize::Elements(size_hint.unwrap_or(1024)));
-        debug_assert!(ids.len() <= std::i8::MAX as usize, "Mask overflow");
-        data.length = ids.len() as i8;
+        // Create query, sized for the components that already exist but never below the default chunk size
+        let mut data = QueryData::new(ChunkSize::Elements(size_hint.unwrap_or(0).max(MIN_CHUNK_SIZE)));
+        data.with_count = ids.terms().iter().filter(|v| matches!(v, QueryTerm::With(_))).count() as i16;
+        data.slot_count = ids.terms().iter().map(|v| v.slot() + 1).max().unwrap_or(0).max(2);
         self.queries.insert(ids.clone(), data);
 
         return ids;
     }
 
-    pub fn update_presence(&mut self, eid: EntityID, id: QueryID, index: u8, attach: bool) {
+    pub fn update_presence(&mut self, eid: EntityID, id: &QueryID, index: u8, attach: bool) {
         assert!((index as usize) < id.len(), "Query index out of range.");
-        match self.queries.get_mut(&id) {
+        let slot = id.terms()[index as usize].slot();
+        match self.queries.get_mut(id) {
             Some(v) => {
-                let mask_old = v.masks.get(eid).copied().unwrap_or(0);
+                let mut mask = v.masks.get(eid).cloned().unwrap_or_else(|| (0..v.slot_count).map(|_| 0).collect());
+                let matched_old = v.matches(&mask);
 
                 // Modify mask
-                let mask_new = mask_old + (if attach { 1 } else  { -1 });
-                debug_assert!(mask_new >= 0,        "Mask dropped below zero"       );
-                debug_assert!(mask_new <= v.length, "Mask raised above query length");
-                v.masks.replace(eid, mask_new);
+                mask[slot] += if attach { 1 } else  { -1 };
+                debug_assert!(mask[slot] >= 0, "Mask dropped below zero");
+                let matched_new = v.matches(&mask);
+                match mask.iter().all(|v| *v == 0) {
+                    true  => { v.masks.remove(eid); },
+                    false => { v.masks.replace(eid, mask); },
+                }
 
                 // Update entities
-                if mask_new == v.length {
-                    if mask_old < v.length { 
-                        v.entities.insert(eid); 
-                    }
-                } else if mask_old >= v.length { 
-                    v.entities.remove(&eid);
+                if matched_new && !matched_old {
+                    v.entities.replace(eid, eid);
+                } else if matched_old && !matched_new {
+                    v.entities.remove(eid);
                 }
             },
             None => { panic!("Query not found!"); }
         }
     }
 
-    pub fn query(&self, id: QueryID, destination: &mut Vec<EntityID>) {
-        match self.queries.get(&id) {
-            Some(v) => { destination.extend(v.entities.iter()) },
+    pub fn query(&self, id: &QueryID, destination: &mut Vec<EntityID>) {
+        destination.extend(self.iter(id));
+    }
+
+    /// Iterates the matching entities in a deterministic order, see QueryData::entities
+    pub fn iter<'a>(&'a self, id: &QueryID) -> impl Iterator<Item = EntityID> + 'a {
+        match self.queries.get(id) {
+            Some(v) => v.entities.iter().copied(),
             None => { panic!("Query not found!"); }
         }
     }
 
 }
-
-
-
-
-
@@ -0,0 +1,174 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::marker::PhantomData;
+
+use butterscotch_codegen::generate_tuple_impls;
+
+use crate::{Component, ComponentAccess, ComponentIDs, ComponentStore, ECS, EntityLocation, QueryTerm, Tick};
+
+/// Limits a query to entities that have a component, without retrieving it
+pub struct With<T: Component>(PhantomData<T>);
+
+/// Limits a query to entities that don't have a component
+pub struct Without<T: Component>(PhantomData<T>);
+
+/// Limits a query to entities that have at least one of a tuple of components, ie. `Or<(A, B)>`
+pub struct Or<T: OrComponents>(PhantomData<T>);
+
+/// Limits a query to entities whose component was attached since the given tick
+pub struct Added<T: Component>(PhantomData<T>);
+
+/// Limits a query to entities whose component was attached or mutably accessed since the given tick
+pub struct Changed<T: Component>(PhantomData<T>);
+
+pub trait QueryFilter {
+    /// Raw pointers to the stores matches reads, taken once before iterating
+    type State: Copy;
+
+    /// Appends the terms of the filter, or-groups are numbered in the order they're encountered
+    fn terms(out: &mut Vec<QueryTerm>, group: &mut u8);
+
+    /// Appends the components the filter reads, for the system scheduler
+    fn accesses(out: &mut Vec<ComponentAccess>);
+
+    fn state(ecs: &ECS) -> Self::State;
+
+    /// Checked per entity while iterating, for conditions that can't be tracked by the query masks.
+    /// Caller must ensure that nothing borrows the stores mutably while matching.
+    unsafe fn matches(_state: Self::State, _at: EntityLocation, _since: Tick) -> bool { true }
+}
+
+pub trait OrComponents {
+    fn ids() -> ComponentIDs;
+    fn accesses(out: &mut Vec<ComponentAccess>);
+}
+
+fn read_access<T: Component>() -> ComponentAccess {
+    ComponentAccess{ id: T::ID, id_str: T::ID_STR, mutable: false }
+}
+
+impl<T: Component> QueryFilter for With<T> {
+    type State = ();
+
+    fn state(_ecs: &ECS) -> Self::State {}
+
+    fn terms(out: &mut Vec<QueryTerm>, _group: &mut u8) {
+        out.push(QueryTerm::With(T::ID));
+    }
+
+    fn accesses(out: &mut Vec<ComponentAccess>) {
+        out.push(read_access::<T>());
+    }
+}
+
+impl<T: Component> QueryFilter for Without<T> {
+    type State = ();
+
+    fn state(_ecs: &ECS) -> Self::State {}
+
+    fn terms(out: &mut Vec<QueryTerm>, _group: &mut u8) {
+        out.push(QueryTerm::Without(T::ID));
+    }
+
+    fn accesses(out: &mut Vec<ComponentAccess>) {
+        out.push(read_access::<T>());
+    }
+}
+
+impl<T: OrComponents> QueryFilter for Or<T> {
+    type State = ();
+
+    fn state(_ecs: &ECS) -> Self::State {}
+
+    fn terms(out: &mut Vec<QueryTerm>, group: &mut u8) {
+        out.extend(T::ids().iter().map(|v| QueryTerm::Or(*group, *v)));
+        *group += 1;
+    }
+
+    fn accesses(out: &mut Vec<ComponentAccess>) {
+        T::accesses(out);
+    }
+}
+
+impl<T: Component> QueryFilter for Added<T> {
+    type State = *const ComponentStore<T>;
+
+    fn state(ecs: &ECS) -> Self::State {
+        unsafe { ecs.get_store_ptr::<T>() }
+    }
+
+    fn terms(out: &mut Vec<QueryTerm>, _group: &mut u8) {
+        out.push(QueryTerm::With(T::ID));
+    }
+
+    fn accesses(out: &mut Vec<ComponentAccess>) {
+        out.push(read_access::<T>());
+    }
+
+    unsafe fn matches(state: Self::State, at: EntityLocation, since: Tick) -> bool {
+        (*state).get_ticks(at).map_or(false, |v| v.is_added(since))
+    }
+}
+
+impl<T: Component> QueryFilter for Changed<T> {
+    type State = *const ComponentStore<T>;
+
+    fn state(ecs: &ECS) -> Self::State {
+        unsafe { ecs.get_store_ptr::<T>() }
+    }
+
+    fn terms(out: &mut Vec<QueryTerm>, _group: &mut u8) {
+        out.push(QueryTerm::With(T::ID));
+    }
+
+    fn accesses(out: &mut Vec<ComponentAccess>) {
+        out.push(read_access::<T>());
+    }
+
+    unsafe fn matches(state: Self::State, at: EntityLocation, since: Tick) -> bool {
+        (*state).get_ticks(at).map_or(false, |v| v.is_changed(since))
+    }
+}
+
+impl QueryFilter for () {
+    type State = ();
+    fn state(_ecs: &ECS) -> Self::State {}
+    fn terms(_out: &mut Vec<QueryTerm>, _group: &mut u8) {}
+    fn accesses(_out: &mut Vec<ComponentAccess>) {}
+}
+
+generate_tuple_impls!(16, r"
+    impl<%{%TR: QueryFilter,%}> QueryFilter for (%{%TR, %}) {
+        type State = (%{%TR::State, %});
+
+        fn state(ecs: &ECS) -> Self::State {(%{
+            %TR::state(ecs),%}
+        )}
+
+        fn terms(out: &mut Vec<QueryTerm>, group: &mut u8) {%{
+            %TR::terms(out, group);%}
+        }
+
+        fn accesses(out: &mut Vec<ComponentAccess>) {%{
+            %TR::accesses(out);%}
+        }
+
+        unsafe fn matches(state: Self::State, at: EntityLocation, since: Tick) -> bool {%{
+            %TR::matches(state.%VI, at, since) &&%} true
+        }
+    }
+
+    impl<%{%TR: Component,%}> OrComponents for (%{%TR, %}) {
+        fn ids() -> ComponentIDs {
+            let mut result = ComponentIDs::new();%{
+            result.push(%TR::ID);%}
+            result
+        }
+
+        fn accesses(out: &mut Vec<ComponentAccess>) {%{
+            out.push(read_access::<%TR>());%}
+        }
+    }
+");
@@ -0,0 +1,251 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::{any::Any, collections::HashMap, fmt};
+
+use crate::{BadIntHasher, Component, ComponentID, EntityID, EntityMap, MapEntities};
+
+/// Values whose fields can be enumerated and accessed by name at runtime, derived with `#[derive(Reflect)]`.
+/// Tuple struct fields are named by their index, so paths look like `transform.position.0`.
+pub trait Reflect: Any {
+    /// The fields of the type, empty for plain values
+    fn field_infos() -> &'static [FieldInfo] where Self: Sized;
+
+    fn type_name(&self) -> &'static str;
+    fn fields(&self) -> &'static [FieldInfo];
+
+    fn field(&self, name: &str)         -> Option<&dyn Reflect>;
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect>;
+
+    fn as_any(&self)         -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Overwrites the whole value, handing the box back if it holds another type
+    fn set(&mut self, value: Box<dyn Any>) -> Result<(), Box<dyn Any>>;
+}
+
+/// The name and type of a reflected field, the type as it's written in the struct
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub type_name: &'static str,
+}
+
+impl FieldInfo {
+    pub const fn new(name: &'static str, type_name: &'static str) -> Self {
+        Self{ name, type_name }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ReflectError {
+    /// The entity is dead or doesn't have the component
+    MissingComponent(ComponentID),
+    /// The component isn't registered with ECS::register_reflect
+    NotReflected(ComponentID),
+    MissingField(String),
+    /// The value passed to set doesn't match the field's type
+    WrongType(&'static str),
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectError::MissingComponent(v) => write!(f, "Entity doesn't have ComponentID({})", v.0),
+            ReflectError::NotReflected(v)     => write!(f, "ComponentID({}) isn't registered for reflection", v.0),
+            ReflectError::MissingField(v)     => write!(f, "No field at \"{}\"", v),
+            ReflectError::WrongType(v)        => write!(f, "Expected a value of type {}", v),
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+impl dyn Reflect {
+
+    /// Follows a dot separated path of field names, the empty path being the value itself
+    pub fn path(&self, path: &str) -> Option<&dyn Reflect> {
+        let mut result = self;
+        for name in path.split('.').filter(|v| !v.is_empty()) {
+            result = result.field(name)?;
+        }
+        return Some(result);
+    }
+
+    pub fn path_mut(&mut self, path: &str) -> Option<&mut dyn Reflect> {
+        let mut result = self;
+        for name in path.split('.').filter(|v| !v.is_empty()) {
+            result = result.field_mut(name)?;
+        }
+        return Some(result);
+    }
+
+    /// Overwrites the field at a path
+    pub fn set_path(&mut self, path: &str, value: Box<dyn Any>) -> Result<(), ReflectError> {
+        let field = self.path_mut(path).ok_or_else(|| ReflectError::MissingField(path.into()))?;
+        let type_name = field.type_name();
+        return field.set(value).map_err(|_| ReflectError::WrongType(type_name));
+    }
+
+    /// Remaps every EntityID reachable through the value's fields, for components that aren't SerializeComponent
+    pub fn map_entities(&mut self, map: &EntityMap) {
+        if let Some(v) = self.downcast_mut::<EntityID>() {
+            v.map_entities(map);
+            return;
+        }
+
+        let fields = self.fields();
+        if fields.is_empty() { // Vec and Option elements are named by their index
+            let mut index = 0;
+            while let Some(v) = self.field_mut(&index.to_string()) {
+                v.map_entities(map);
+                index += 1;
+            }
+        }
+        for field in fields {
+            if let Some(v) = self.field_mut(field.name) { v.map_entities(map); }
+        }
+    }
+
+    pub fn downcast_ref<T: Reflect>(&self) -> Option<&T> {
+        self.as_any().downcast_ref::<T>()
+    }
+
+    pub fn downcast_mut<T: Reflect>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut::<T>()
+    }
+}
+
+// // Plain Values // //
+
+macro_rules! impl_reflect_value {
+    ($($t:ty),*) => {$(
+        impl Reflect for $t {
+            fn field_infos() -> &'static [FieldInfo] { &[] }
+
+            fn type_name(&self) -> &'static str { stringify!($t) }
+            fn fields(&self) -> &'static [FieldInfo] { &[] }
+
+            fn field(&self, _name: &str)         -> Option<&dyn Reflect>     { None }
+            fn field_mut(&mut self, _name: &str) -> Option<&mut dyn Reflect> { None }
+
+            fn as_any(&self)         -> &dyn Any     { self }
+            fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+            fn set(&mut self, value: Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+                *self = *value.downcast::<Self>()?;
+                return Ok(());
+            }
+        }
+    )*};
+}
+
+impl_reflect_value!(bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, String, &'static str, EntityID);
+
+/// The value, if any, is a field named 0
+impl<T: Reflect> Reflect for Option<T> {
+    fn field_infos() -> &'static [FieldInfo] { &[] }
+
+    fn type_name(&self) -> &'static str { std::any::type_name::<Self>() }
+    fn fields(&self) -> &'static [FieldInfo] { &[] }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        match name {
+            "0" => self.as_ref().map(|v| v as &dyn Reflect),
+            _   => None,
+        }
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        match name {
+            "0" => self.as_mut().map(|v| v as &mut dyn Reflect),
+            _   => None,
+        }
+    }
+
+    fn as_any(&self)         -> &dyn Any     { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn set(&mut self, value: Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+        *self = *value.downcast::<Self>()?;
+        return Ok(());
+    }
+}
+
+/// Elements are fields named by their index
+impl<T: Reflect> Reflect for Vec<T> {
+    fn field_infos() -> &'static [FieldInfo] { &[] }
+
+    fn type_name(&self) -> &'static str { std::any::type_name::<Self>() }
+    fn fields(&self) -> &'static [FieldInfo] { &[] }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        self.get(name.parse::<usize>().ok()?).map(|v| v as &dyn Reflect)
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        self.get_mut(name.parse::<usize>().ok()?).map(|v| v as &mut dyn Reflect)
+    }
+
+    fn as_any(&self)         -> &dyn Any     { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn set(&mut self, value: Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+        *self = *value.downcast::<Self>()?;
+        return Ok(());
+    }
+}
+
+// // Registry // //
+
+/// A reflected component, found by ID or ID_STR
+#[derive(Debug, Clone, Copy)]
+pub struct TypeRegistration {
+    pub id: ComponentID,
+    pub id_str: &'static str,
+    pub type_name: &'static str,
+    pub fields: &'static [FieldInfo],
+}
+
+/// Every component registered with ECS::register_reflect
+#[derive(Debug, Default)]
+pub struct TypeRegistry {
+    entries: Vec<TypeRegistration>,
+    by_id: HashMap<ComponentID, usize, BadIntHasher>,
+    by_name: HashMap<&'static str, usize>,
+}
+
+impl TypeRegistry {
+    pub(crate) fn register<T: Component + Reflect>(&mut self) {
+        let result = self.by_name.insert(T::ID_STR, self.entries.len());
+        assert!(result.is_none(), "Component \"{}\" registered for reflection more than once", T::ID_STR);
+        self.by_id.insert(T::ID, self.entries.len());
+        self.entries.push(TypeRegistration{ id: T::ID, id_str: T::ID_STR, type_name: std::any::type_name::<T>(), fields: T::field_infos() });
+    }
+
+    pub fn get(&self, id: ComponentID) -> Option<&TypeRegistration> {
+        self.by_id.get(&id).map(|v| &self.entries[*v])
+    }
+
+    pub fn get_by_name(&self, id_str: &str) -> Option<&TypeRegistration> {
+        self.by_name.get(id_str).map(|v| &self.entries[*v])
+    }
+
+    pub fn contains(&self, id: ComponentID) -> bool {
+        self.by_id.contains_key(&id)
+    }
+
+    /// Iterates in registration order
+    pub fn iter(&self) -> impl Iterator<Item = &TypeRegistration> {
+        self.entries.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
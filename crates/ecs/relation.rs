@@ -0,0 +1,173 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::{any::Any, collections::HashSet, fmt::Debug};
+
+use butterscotch_common::container::{ChunkSize, GIDStore};
+
+use crate::EntityID;
+
+#[repr(transparent)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct RelationID(pub u16);
+
+/// Data attached to a pair of entities, ie. `ecs.relate(player, enemy, Targets)`
+pub trait Relation: Any + Debug + Send + Sync {
+    const ID: RelationID;
+    const ID_STR: &'static str;
+}
+
+pub trait RelationStoreAny: Any + Debug {
+    fn relation_id(&self)     -> RelationID;
+    fn relation_id_str(&self) -> &'static str;
+
+    fn as_any(&self)         -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Relates a pair with a boxed value, replacing any previous value. Panics if the box doesn't hold the store's type
+    fn insert_boxed(&mut self, source: EntityID, target: EntityID, value: Box<dyn Any>);
+
+    fn remove_pair(&mut self, source: EntityID, target: EntityID) -> bool;
+
+    /// Unrelates a pair, handing back its value
+    fn take_boxed(&mut self, source: EntityID, target: EntityID) -> Option<Box<dyn Any>>;
+
+    /// Every pair with both ends among the entities, grouped by source in the order the entities are given
+    fn pairs_among(&self, entities: &[EntityID]) -> Vec<(EntityID, EntityID)>;
+
+    /// Drops every pair the entity is on either side of
+    fn remove_entity(&mut self, eid: EntityID);
+}
+
+/// Every pair of a relation, indexed from both sides
+#[derive(Debug)]
+pub struct RelationStore<R: Relation> {
+    targets: GIDStore<Vec<(EntityID, R)>>,
+    sources: GIDStore<Vec<EntityID>>,
+    len: usize,
+}
+
+impl<R: Relation> RelationStoreAny for RelationStore<R> {
+    fn relation_id(&self)     -> RelationID   { R::ID     }
+    fn relation_id_str(&self) -> &'static str { R::ID_STR }
+
+    fn as_any(&self)         -> &dyn Any     { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+    fn insert_boxed(&mut self, source: EntityID, target: EntityID, value: Box<dyn Any>) {
+        match value.downcast::<R>() {
+            Ok(v)  => { self.insert(source, target, *v); },
+            Err(_) => panic!("Boxed relation passed to the store of \"{}\" has a different type", R::ID_STR),
+        }
+    }
+
+    fn remove_pair(&mut self, source: EntityID, target: EntityID) -> bool {
+        self.remove(source, target).is_some()
+    }
+
+    fn take_boxed(&mut self, source: EntityID, target: EntityID) -> Option<Box<dyn Any>> {
+        let value = self.remove(source, target)?;
+        Some(box value)
+    }
+
+    fn pairs_among(&self, entities: &[EntityID]) -> Vec<(EntityID, EntityID)> {
+        let among = entities.iter().copied().collect::<HashSet<_>>();
+        let mut result = Vec::new();
+        for source in entities {
+            result.extend(self.targets(*source).map(|v| v.0).filter(|v| among.contains(v)).map(|v| (*source, v)));
+        }
+        return result;
+    }
+
+    fn remove_entity(&mut self, eid: EntityID) {
+        for (target, _) in self.targets.remove(eid).unwrap_or_default() {
+            Self::unlink(&mut self.sources, target, eid);
+            self.len -= 1;
+        }
+        for source in self.sources.remove(eid).unwrap_or_default() {
+            let targets = self.targets.get_mut(source).unwrap();
+            targets.retain(|v| v.0 != eid);
+            if targets.is_empty() { self.targets.remove(source); }
+            self.len -= 1;
+        }
+    }
+}
+
+impl<R: Relation> RelationStore<R> {
+
+    pub fn new(chunk_size: ChunkSize) -> Self {
+        Self{ targets: GIDStore::new(chunk_size), sources: GIDStore::new(chunk_size), len: 0 }
+    }
+
+    /// Relates a pair, returning the previous value if they were already related
+    pub fn insert(&mut self, source: EntityID, target: EntityID, value: R) -> Option<R> {
+        if let Some(v) = self.get_mut(source, target) {
+            return Some(std::mem::replace(v, value));
+        }
+
+        match self.targets.get_mut(source) {
+            Some(v) => v.push((target, value)),
+            None    => { self.targets.replace(source, vec![(target, value)]); },
+        }
+        match self.sources.get_mut(target) {
+            Some(v) => v.push(source),
+            None    => { self.sources.replace(target, vec![source]); },
+        }
+        self.len += 1;
+        return None;
+    }
+
+    pub fn remove(&mut self, source: EntityID, target: EntityID) -> Option<R> {
+        let targets = self.targets.get_mut(source)?;
+        let index = targets.iter().position(|v| v.0 == target)?;
+        let (_, value) = targets.remove(index);
+        if targets.is_empty() { self.targets.remove(source); }
+
+        Self::unlink(&mut self.sources, target, source);
+        self.len -= 1;
+        return Some(value);
+    }
+
+    pub fn contains(&self, source: EntityID, target: EntityID) -> bool {
+        self.get(source, target).is_some()
+    }
+
+    pub fn get(&self, source: EntityID, target: EntityID) -> Option<&R> {
+        self.targets.get(source)?.iter().find(|v| v.0 == target).map(|v| &v.1)
+    }
+
+    pub fn get_mut(&mut self, source: EntityID, target: EntityID) -> Option<&mut R> {
+        self.targets.get_mut(source)?.iter_mut().find(|v| v.0 == target).map(|v| &mut v.1)
+    }
+
+    /// Every entity the source is related to, along with the relation's value, in the order they were related
+    pub fn targets(&self, source: EntityID) -> impl Iterator<Item = (EntityID, &R)> {
+        self.targets.get(source).into_iter().flatten().map(|v| (v.0, &v.1))
+    }
+
+    /// Every entity related to the target, in the order they were related
+    pub fn sources(&self, target: EntityID) -> impl Iterator<Item = EntityID> + '_ {
+        self.sources.get(target).into_iter().flatten().copied()
+    }
+
+    /// Every related pair along with its value, grouped by source
+    pub fn pairs(&self) -> impl Iterator<Item = (EntityID, EntityID, &R)> {
+        self.targets.keys().zip(self.targets.iter()).flat_map(|(source, targets)| targets.iter().map(move |v| (source, v.0, &v.1)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of related pairs
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    fn unlink(index: &mut GIDStore<Vec<EntityID>>, key: EntityID, eid: EntityID) {
+        let entities = index.get_mut(key).expect("Relation indices out of sync");
+        entities.retain(|v| *v != eid);
+        if entities.is_empty() { index.remove(key); }
+    }
+}
@@ -0,0 +1,94 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::{any::{Any, TypeId}, cell::UnsafeCell};
+
+/// A singleton stored in the ECS, keyed by its type. Shared between the threads of the system scheduler.
+pub trait Resource: Any + Send + Sync {}
+
+impl<T: Any + Send + Sync> Resource for T {}
+
+/// A single resource used by a scheduled system, and how it is borrowed
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ResourceAccess {
+    pub id: TypeId,
+    pub id_str: &'static str,
+    pub mutable: bool,
+}
+
+impl ResourceAccess {
+    pub fn read<R: Resource>() -> Self {
+        Self{ id: TypeId::of::<R>(), id_str: std::any::type_name::<R>(), mutable: false }
+    }
+
+    pub fn write<R: Resource>() -> Self {
+        Self{ id: TypeId::of::<R>(), id_str: std::any::type_name::<R>(), mutable: true }
+    }
+}
+
+/// Every resource sits in an UnsafeCell so the system scheduler can write to disjoint resources through a shared ECS
+type ResourceCell = Box<UnsafeCell<dyn Any + Send + Sync>>;
+
+#[derive(Debug, Default)]
+pub struct Resources {
+    values: std::collections::HashMap<TypeId, ResourceCell>,
+}
+
+impl Resources {
+
+    /// Inserts a resource, returning the previous value if there was one
+    pub fn insert<R: Resource>(&mut self, value: R) -> Option<R> {
+        let value = UnsafeCell::new(value);
+        self.values.insert(TypeId::of::<R>(), box value).map(|v| unsafe { Self::unwrap::<R>(v) })
+    }
+
+    /// Inserts a boxed resource under its own type, replacing any previous value
+    pub fn insert_boxed(&mut self, value: Box<dyn Any + Send + Sync>) {
+        let id = (*value).type_id();
+        let value = unsafe { Box::from_raw(Box::into_raw(value) as *mut UnsafeCell<dyn Any + Send + Sync>) }; // UnsafeCell is repr(transparent)
+        self.values.insert(id, value);
+    }
+
+    pub fn remove<R: Resource>(&mut self) -> Option<R> {
+        self.values.remove(&TypeId::of::<R>()).map(|v| unsafe { Self::unwrap::<R>(v) })
+    }
+
+    pub fn remove_id(&mut self, id: TypeId) -> bool {
+        self.values.remove(&id).is_some()
+    }
+
+    pub fn contains<R: Resource>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn get<R: Resource>(&self) -> Option<&R> {
+        self.values.get(&TypeId::of::<R>()).and_then(|v| unsafe { &*v.get() }.downcast_ref::<R>())
+    }
+
+    pub fn get_mut<R: Resource>(&mut self) -> Option<&mut R> {
+        self.values.get_mut(&TypeId::of::<R>()).and_then(|v| v.get_mut().downcast_mut::<R>())
+    }
+
+    /// Caller must ensure that the resource isn't borrowed elsewhere while the pointer is dereferenced mutably
+    pub(crate) unsafe fn get_ptr<R: Resource>(&self) -> Option<*mut R> {
+        let value = self.values.get(&TypeId::of::<R>())?.get();
+        match (*value).is::<R>() {
+            true  => Some(value as *mut R),
+            false => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Caller must ensure that the cell holds an R, which is the case for every cell stored under TypeId::of::<R>()
+    unsafe fn unwrap<R: Resource>(value: ResourceCell) -> R {
+        Box::from_raw(Box::into_raw(value) as *mut UnsafeCell<R>).into_inner()
+    }
+}
@@ -0,0 +1,270 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::{Commands, Component, ComponentAccess, ComponentID, ComponentIDs, ComponentMutRequestTupleDefinition, ComponentRequestTupleDefinition, ECS, EntityID, OptRefComponents, QueryFilter, Resource, ResourceAccess, Tick, Trigger};
+
+/// A system that runs every time the schedule is run, over the components it declares.
+/// Systems whose accesses don't conflict are run at the same time.
+pub trait ScheduledSystem: Send + 'static {
+    /// Name used in diagnostics
+    fn name(&self) -> &'static str { std::any::type_name::<Self>() }
+
+    /// Every component the system reads or writes, queried once on registration.
+    /// Components named by filters are read too, so they must be declared, accesses_of includes them.
+    fn accesses(&self) -> Vec<ComponentAccess>;
+
+    /// Every resource the system reads or writes, queried once on registration
+    fn resource_accesses(&self) -> Vec<ResourceAccess> { Vec::new() }
+
+    fn run(&mut self, context: &mut SystemContext);
+}
+
+/// The accesses of a request, ie. `accesses_of::<((Mut<A>,), (B,))>()` for use in ScheduledSystem::accesses.
+/// Components only named by the filter are read.
+pub fn accesses_of<'a, T: ComponentMutRequestTupleDefinition<'a>>() -> Vec<ComponentAccess> {
+    let mut result = T::accesses();
+    let mut filters = Vec::new();
+    T::Filter::accesses(&mut filters);
+    for access in filters {
+        if !result.iter().any(|v| v.id == access.id) { result.push(access); }
+    }
+    return result;
+}
+
+/// A system's view of the ECS, limited to the components it declared.
+/// Structural changes go through commands, which are applied once every system in the stage has run.
+pub struct SystemContext<'a> {
+    ecs: SharedECS,
+    name: &'static str,
+    accesses: &'a [ComponentAccess],
+    resource_accesses: &'a [ResourceAccess],
+    last_run: Tick,
+    mutations: Vec<(Trigger, EntityID)>,
+    commands: Commands,
+}
+
+/// The ECS the systems of a stage share, the rest of SystemContext is Send on its own
+#[derive(Clone, Copy)]
+struct SharedECS(*mut ECS);
+
+// Systems only reach the ECS through SystemContext, which limits them to the component stores and resources they
+// declared. Those are Send + Sync, and systems in a stage never declare conflicting accesses. The parts of the ECS
+// that aren't Send, ie. reactive systems and subscriptions, are only touched between stages on the calling thread.
+unsafe impl Send for SharedECS {}
+
+impl<'a> SystemContext<'a> {
+
+    /// The tick the system last ran in, for use with query_since
+    pub fn last_run(&self) -> Tick {
+        self.last_run
+    }
+
+    pub fn commands(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+
+    /// Added and Changed filters match changes made since the system last ran
+    pub fn query<'b, T: ComponentRequestTupleDefinition<'b> + 'b>(&'b self) -> impl Iterator<Item = (EntityID, T::ReqRefComponentTuple, T::OptRefComponentTuple)> + 'b {
+        self.query_since::<T>(self.last_run)
+    }
+
+    pub fn query_since<'b, T: ComponentRequestTupleDefinition<'b> + 'b>(&'b self, since: Tick) -> impl Iterator<Item = (EntityID, T::ReqRefComponentTuple, T::OptRefComponentTuple)> + 'b {
+        let mut ids = T::query_id().terms().iter().map(|v| v.component_id()).collect::<ComponentIDs>();
+        T::OptRefComponentTuple::ids(&mut ids);
+        for id in ids {
+            self.assert_declared(id, false, std::any::type_name::<T>());
+        }
+        unsafe { &*self.ecs.0 }.query_since::<T>(since)
+    }
+
+    pub fn query_mut<'b, T: ComponentMutRequestTupleDefinition<'b> + 'b>(&'b mut self) -> impl Iterator<Item = (EntityID, T::ReqMutComponentTuple, T::OptMutComponentTuple)> + 'b {
+        self.query_mut_since::<T>(self.last_run)
+    }
+
+    pub fn query_mut_since<'b, T: ComponentMutRequestTupleDefinition<'b> + 'b>(&'b mut self, since: Tick) -> impl Iterator<Item = (EntityID, T::ReqMutComponentTuple, T::OptMutComponentTuple)> + 'b {
+        let accesses = T::accesses();
+        for access in &accesses {
+            self.assert_declared(access.id, access.mutable, std::any::type_name::<T>());
+        }
+        for term in T::query_id().terms() {
+            self.assert_declared(term.component_id(), false, std::any::type_name::<T>());
+        }
+
+        // Mutations are kept local until the stage is done, the log is shared between systems
+        let ecs = self.ecs.0;
+        let mutations = &mut self.mutations;
+        unsafe { (*ecs).query_mut_raw::<T>(since) }.map(move |v| {
+            for access in accesses.iter().filter(|a| a.mutable && unsafe { &*ecs }.has_id(v.0, a.id)) {
+                mutations.push((Trigger::Mutate(access.id), v.0));
+            }
+            v
+        })
+    }
+
+    pub fn get_ref<T: Component>(&self, eid: EntityID) -> Option<&T> {
+        self.assert_declared(T::ID, false, T::ID_STR);
+        unsafe { &*self.ecs.0 }.get_ref::<T>(eid)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, eid: EntityID) -> Option<&mut T> {
+        self.assert_declared(T::ID, true, T::ID_STR);
+        let ecs = unsafe { &*self.ecs.0 };
+        let location = ecs.archetypes().location(eid)?;
+        let result = unsafe { (*ecs.get_store_ptr::<T>()).get_mut(location) }?;
+        self.mutations.push((Trigger::Mutate(T::ID), eid));
+        Some(result)
+    }
+
+    pub fn resource<R: Resource>(&self) -> Option<&R> {
+        self.assert_resource_declared::<R>(false);
+        unsafe { &*self.ecs.0 }.resource::<R>()
+    }
+
+    pub fn resource_mut<R: Resource>(&mut self) -> Option<&mut R> {
+        self.assert_resource_declared::<R>(true);
+        unsafe { (*self.ecs.0).resource_ptr::<R>().map(|v| &mut *v) }
+    }
+
+    fn assert_resource_declared<R: Resource>(&self, mutable: bool) {
+        let access = ResourceAccess::read::<R>();
+        let declared = self.resource_accesses.iter().any(|v| v.id == access.id && (v.mutable || !mutable));
+        assert!(declared, "System \"{}\" didn't declare {} access to resource \"{}\"", self.name, if mutable { "mutable" } else { "immutable" }, access.id_str);
+    }
+
+    fn assert_declared(&self, id: ComponentID, mutable: bool, request: &str) {
+        let declared = self.accesses.iter().any(|v| v.id == id && (v.mutable || !mutable));
+        assert!(declared, "System \"{}\" didn't declare {} access to ComponentID({}) used by \"{}\"", self.name, if mutable { "mutable" } else { "immutable" }, id.0, request);
+    }
+}
+
+struct ScheduledEntry {
+    system: Box<dyn ScheduledSystem>,
+    accesses: Vec<ComponentAccess>,
+    resource_accesses: Vec<ResourceAccess>,
+    stage: usize,
+    last_run: Tick,
+}
+
+/// Runs systems in stages, every stage holding systems that don't conflict with each other.
+/// Two systems conflict when they share a component or resource and either of them writes it, a system
+/// always runs after every conflicting system registered before it.
+pub struct Scheduler {
+    entries: Vec<ScheduledEntry>,
+    stages: usize,
+    pool: Option<ThreadPool>,
+}
+
+impl std::fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("systems", &self.entries.iter().map(|v| (v.system.name(), v.stage)).collect::<Vec<_>>())
+            .field("threads", &self.threads())
+            .finish()
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+
+    /// Uses a thread per core
+    pub fn new() -> Self {
+        Self::with_threads(0)
+    }
+
+    /// Uses the given number of threads, zero for a thread per core and one to run every system on the calling thread
+    pub fn with_threads(threads: usize) -> Self {
+        let pool = match threads {
+            1 => None,
+            _ => Some(ThreadPoolBuilder::new().num_threads(threads).build().expect("Failed to create the scheduler's thread pool")),
+        };
+        Self{ entries: Vec::new(), stages: 0, pool }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.as_ref().map_or(1, |v| v.current_num_threads())
+    }
+
+    pub fn add_system<S: ScheduledSystem>(&mut self, system: S) {
+        let accesses = system.accesses();
+        let resource_accesses = system.resource_accesses();
+        let stage = self.entries.iter()
+            .filter(|v| Self::conflicts(&v.accesses, &accesses) || Self::resource_conflicts(&v.resource_accesses, &resource_accesses))
+            .map(|v| v.stage + 1)
+            .max()
+            .unwrap_or(0);
+
+        self.stages = self.stages.max(stage + 1);
+        self.entries.push(ScheduledEntry{ system: box system, accesses, resource_accesses, stage, last_run: Tick::default() });
+    }
+
+    /// Both access the same component, and at least one of them writes it
+    pub fn conflicts(a: &[ComponentAccess], b: &[ComponentAccess]) -> bool {
+        a.iter().any(|a| b.iter().any(|b| a.id == b.id && (a.mutable || b.mutable)))
+    }
+
+    /// Both access the same resource, and at least one of them writes it
+    pub fn resource_conflicts(a: &[ResourceAccess], b: &[ResourceAccess]) -> bool {
+        a.iter().any(|a| b.iter().any(|b| a.id == b.id && (a.mutable || b.mutable)))
+    }
+
+    /// The names of the systems in each stage, in the order they're run
+    pub fn stages(&self) -> Vec<Vec<&'static str>> {
+        let mut result = vec![Vec::new(); self.stages];
+        for entry in &self.entries {
+            result[entry.stage].push(entry.system.name());
+        }
+        return result;
+    }
+
+    /// Runs every system once. Each stage's commands and mutations are applied in registration order once
+    /// the whole stage has run, so the result doesn't depend on the number of threads.
+    pub fn run(&mut self, ecs: &mut ECS) {
+        for stage in 0..self.stages {
+            let ecs_ptr: *mut ECS = ecs;
+            let mut contexts = self.entries.iter_mut().filter(|v| v.stage == stage).map(|entry| {
+                let context = SystemContext{
+                    ecs: SharedECS(ecs_ptr),
+                    name: entry.system.name(),
+                    accesses: &entry.accesses,
+                    resource_accesses: &entry.resource_accesses,
+                    last_run: entry.last_run,
+                    mutations: Vec::new(),
+                    commands: Commands::new(),
+                };
+                (&mut entry.system, context)
+            }).collect::<Vec<_>>();
+
+            match &self.pool {
+                Some(pool) if contexts.len() > 1 => pool.scope(|scope| {
+                    for (system, context) in contexts.iter_mut() {
+                        scope.spawn(move |_| system.run(context));
+                    }
+                }),
+                _ => for (system, context) in contexts.iter_mut() {
+                    system.run(context);
+                },
+            }
+
+            // Finalize the stage, the commands are newer than the run
+            let results = contexts.into_iter().map(|(_, v)| (v.mutations, v.commands)).collect::<Vec<_>>();
+            let run = ecs.next_change_tick();
+            for entry in self.entries.iter_mut().filter(|v| v.stage == stage) {
+                entry.last_run = run;
+            }
+            for (mutations, commands) in results {
+                for (trigger, eid) in mutations {
+                    ecs.record_mutation(trigger, eid);
+                }
+                ecs.apply(commands);
+            }
+        }
+    }
+}
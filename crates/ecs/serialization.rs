@@ -0,0 +1,482 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::{any::Any, collections::HashMap, fmt};
+
+use serde::{Serialize, Serializer, de::{self, DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor}, ser::{SerializeMap, SerializeSeq, SerializeTuple}};
+
+use crate::{BadIntHasher, Component, ComponentID, ECS, EntityID, Relation, RelationID};
+
+/// Components that are saved along with the world, opted into with `#[component(serialize)]` when deriving Component.
+/// Saves identify components by ID_STR, so renaming a component or its namespace breaks older saves.
+pub trait SerializeComponent: Component + Serialize + DeserializeOwned {
+    /// Remaps every EntityID the component holds once it's been loaded
+    fn map_entities(&mut self, _map: &EntityMap) {}
+}
+
+/// Relations that are saved along with the world, identified by ID_STR like components.
+/// Pairs are only loaded when both of their entities were part of the save.
+pub trait SerializeRelation: Relation + Serialize + DeserializeOwned {
+    /// Remaps every EntityID the value holds once it's been loaded
+    fn map_entities(&mut self, _map: &EntityMap) {}
+}
+
+/// Values holding EntityIDs, for remapping them by hand in SerializeComponent::map_entities
+pub trait MapEntities {
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+impl MapEntities for EntityID {
+    fn map_entities(&mut self, map: &EntityMap) {
+        *self = map.get(*self);
+    }
+}
+
+impl<T: MapEntities> MapEntities for Option<T> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        if let Some(v) = self { v.map_entities(map); }
+    }
+}
+
+impl<T: MapEntities> MapEntities for Vec<T> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for v in self { v.map_entities(map); }
+    }
+}
+
+/// The entities of a save paired with the entities they were loaded as
+#[derive(Debug, Default, Clone)]
+pub struct EntityMap {
+    entities: HashMap<EntityID, EntityID>,
+}
+
+impl EntityMap {
+    pub fn insert(&mut self, saved: EntityID, loaded: EntityID) {
+        self.entities.insert(saved, loaded);
+    }
+
+    /// The entity a saved entity was loaded as, or an invalid ID if it wasn't part of the save
+    pub fn get(&self, saved: EntityID) -> EntityID {
+        self.entities.get(&saved).copied().unwrap_or_else(EntityID::new)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityID, EntityID)> + '_ {
+        self.entities.iter().map(|(k, v)| (*k, *v))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+}
+
+#[derive(Debug)]
+pub enum SerializationError {
+    Binary(bincode::Error),
+    Text(ron::Error),
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializationError::Binary(v) => write!(f, "Binary world serialization failed: {}", v),
+            SerializationError::Text(v)   => write!(f, "Text world serialization failed: {}", v),
+        }
+    }
+}
+
+impl std::error::Error for SerializationError {}
+
+impl From<bincode::Error> for SerializationError {
+    fn from(error: bincode::Error) -> Self {
+        SerializationError::Binary(error)
+    }
+}
+
+impl From<ron::Error> for SerializationError {
+    fn from(error: ron::Error) -> Self {
+        SerializationError::Text(error)
+    }
+}
+
+// // Registry // //
+
+type SerializeFn   = fn(&ECS, EntityID) -> Option<&dyn erased_serde::Serialize>;
+type DeserializeFn = fn(&mut dyn erased_serde::Deserializer<'_>) -> Result<Box<dyn Any>, erased_serde::Error>;
+type MapEntitiesFn = fn(&mut dyn Any, &EntityMap);
+type PairsFn       = fn(&ECS) -> Vec<(EntityID, EntityID, &dyn erased_serde::Serialize)>;
+
+struct ComponentSerializer {
+    id: ComponentID,
+    id_str: &'static str,
+    serialize: SerializeFn,
+    deserialize: DeserializeFn,
+    map_entities: MapEntitiesFn,
+}
+
+struct RelationSerializer {
+    id: RelationID,
+    id_str: &'static str,
+    pairs: PairsFn,
+    deserialize: DeserializeFn,
+    map_entities: MapEntitiesFn,
+}
+
+/// The serializable components and relations, found by ID when saving and by ID_STR when loading
+#[derive(Default)]
+pub(crate) struct Serializers {
+    entries: Vec<ComponentSerializer>,
+    by_id: HashMap<ComponentID, usize, BadIntHasher>,
+    by_name: HashMap<&'static str, usize>,
+    relations: Vec<RelationSerializer>,
+    relations_by_id: HashMap<RelationID, usize, BadIntHasher>,
+    relations_by_name: HashMap<&'static str, usize>,
+}
+
+impl fmt::Debug for Serializers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.entries.iter().map(|v| v.id_str).chain(self.relations.iter().map(|v| v.id_str))).finish()
+    }
+}
+
+impl Serializers {
+    pub fn register<T: SerializeComponent>(&mut self) {
+        let result = self.by_name.insert(T::ID_STR, self.entries.len());
+        assert!(result.is_none(), "Component \"{}\" registered for serialization more than once", T::ID_STR);
+        self.by_id.insert(T::ID, self.entries.len());
+        self.entries.push(ComponentSerializer{
+            id: T::ID,
+            id_str: T::ID_STR,
+            serialize: |ecs, eid| ecs.get_ref::<T>(eid).map(|v| v as &dyn erased_serde::Serialize),
+            deserialize: |deserializer| erased_serde::deserialize::<T>(deserializer).map(|v| box v as Box<dyn Any>),
+            map_entities: |value, map| value.downcast_mut::<T>().unwrap().map_entities(map),
+        });
+    }
+
+    pub fn register_relation<R: SerializeRelation>(&mut self) {
+        let result = self.relations_by_name.insert(R::ID_STR, self.relations.len());
+        assert!(result.is_none(), "Relation \"{}\" registered for serialization more than once", R::ID_STR);
+        self.relations_by_id.insert(R::ID, self.relations.len());
+        self.relations.push(RelationSerializer{
+            id: R::ID,
+            id_str: R::ID_STR,
+            pairs: |ecs| {
+                let mut result = ecs.relations::<R>().pairs().map(|(source, target, value)| (source, target, value as &dyn erased_serde::Serialize)).collect::<Vec<_>>();
+                result.sort_unstable_by_key(|v| (v.0, v.1));
+                result
+            },
+            deserialize: |deserializer| erased_serde::deserialize::<R>(deserializer).map(|v| box v as Box<dyn Any>),
+            map_entities: |value, map| value.downcast_mut::<R>().unwrap().map_entities(map),
+        });
+    }
+
+    pub fn contains(&self, id: ComponentID) -> bool {
+        self.by_id.contains_key(&id)
+    }
+
+    /// Remaps the EntityIDs held by a loaded component
+    pub fn map_entities(&self, id: ComponentID, value: &mut dyn Any, map: &EntityMap) {
+        (self.entries[self.by_id[&id]].map_entities)(value, map);
+    }
+
+    pub fn contains_relation(&self, id: RelationID) -> bool {
+        self.relations_by_id.contains_key(&id)
+    }
+
+    /// Remaps the EntityIDs held by a loaded relation, relations that aren't serializable are left as they are
+    pub fn map_relation(&self, id: RelationID, value: &mut dyn Any, map: &EntityMap) {
+        if let Some(v) = self.relations_by_id.get(&id) { (self.relations[*v].map_entities)(value, map); }
+    }
+}
+
+// // Saving // //
+
+/// Serializes every entity as its ID followed by a map of its serializable components keyed by ID_STR, then the pairs
+/// of every serializable relation keyed by ID_STR
+pub(crate) struct WorldRef<'a> {
+    pub ecs: &'a ECS,
+    pub serializers: &'a Serializers,
+}
+
+struct EntitiesRef<'a>(&'a WorldRef<'a>);
+
+struct RelationsRef<'a>(&'a WorldRef<'a>);
+
+struct ComponentsRef<'a> {
+    world: &'a WorldRef<'a>,
+    eid: EntityID,
+    components: Vec<usize>,
+}
+
+impl<'a> Serialize for WorldRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&EntitiesRef(self))?;
+        tuple.serialize_element(&RelationsRef(self))?;
+        tuple.end()
+    }
+}
+
+impl<'a> Serialize for EntitiesRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let world = self.0;
+        let mut entities = world.ecs.archetypes().iter().flat_map(|v| v.entities().iter().copied()).collect::<Vec<_>>();
+        entities.sort_unstable();
+
+        let mut seq = serializer.serialize_seq(Some(entities.len()))?;
+        for eid in entities {
+            let location = world.ecs.archetypes().location(eid).unwrap();
+            let components = world.ecs.archetypes().get(location.archetype).components().iter()
+                .filter_map(|v| world.serializers.by_id.get(v).copied())
+                .collect();
+            seq.serialize_element(&(eid, ComponentsRef{ world, eid, components }))?;
+        }
+        seq.end()
+    }
+}
+
+impl<'a> Serialize for RelationsRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let world = self.0;
+        let mut map = serializer.serialize_map(Some(world.serializers.relations.len()))?;
+        for entry in &world.serializers.relations {
+            map.serialize_entry(entry.id_str, &(entry.pairs)(world.ecs))?;
+        }
+        map.end()
+    }
+}
+
+impl<'a> Serialize for ComponentsRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.components.len()))?;
+        for index in &self.components {
+            let entry = &self.world.serializers.entries[*index];
+            map.serialize_entry(entry.id_str, (entry.serialize)(self.world.ecs, self.eid).unwrap())?;
+        }
+        map.end()
+    }
+}
+
+// // Loading // //
+
+/// Entities and relations as they were saved, they have yet to be remapped
+pub(crate) struct LoadedWorld {
+    pub entities: Vec<LoadedEntity>,
+    pub relations: Vec<LoadedRelation>,
+}
+
+pub(crate) struct LoadedEntity {
+    pub eid: EntityID,
+    pub components: Vec<(ComponentID, Box<dyn Any>)>,
+}
+
+pub(crate) struct LoadedRelation {
+    pub id: RelationID,
+    pub source: EntityID,
+    pub target: EntityID,
+    pub value: Box<dyn Any>,
+}
+
+pub(crate) struct WorldSeed<'a>(pub &'a Serializers);
+
+impl<'a, 'de> DeserializeSeed<'de> for WorldSeed<'a> {
+    type Value = LoadedWorld;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_tuple(2, self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for WorldSeed<'a> {
+    type Value = LoadedWorld;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of entities followed by a map of relations")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let entities = seq.next_element_seed(EntitiesSeed(self.0))?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let relations = seq.next_element_seed(RelationsSeed(self.0))?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        return Ok(LoadedWorld{ entities, relations });
+    }
+}
+
+struct EntitiesSeed<'a>(&'a Serializers);
+
+impl<'a, 'de> DeserializeSeed<'de> for EntitiesSeed<'a> {
+    type Value = Vec<LoadedEntity>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for EntitiesSeed<'a> {
+    type Value = Vec<LoadedEntity>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of entities")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut result = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(v) = seq.next_element_seed(EntitySeed(self.0))? {
+            result.push(v);
+        }
+        return Ok(result);
+    }
+}
+
+struct EntitySeed<'a>(&'a Serializers);
+
+impl<'a, 'de> DeserializeSeed<'de> for EntitySeed<'a> {
+    type Value = LoadedEntity;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_tuple(2, self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for EntitySeed<'a> {
+    type Value = LoadedEntity;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an entity ID followed by its components")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let eid = seq.next_element::<EntityID>()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let components = seq.next_element_seed(ComponentsSeed(self.0))?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        return Ok(LoadedEntity{ eid, components });
+    }
+}
+
+struct ComponentsSeed<'a>(&'a Serializers);
+
+impl<'a, 'de> DeserializeSeed<'de> for ComponentsSeed<'a> {
+    type Value = Vec<(ComponentID, Box<dyn Any>)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for ComponentsSeed<'a> {
+    type Value = Vec<(ComponentID, Box<dyn Any>)>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of components keyed by ID_STR")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut result = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some(name) = map.next_key::<String>()? {
+            let entry = match self.0.by_name.get(name.as_str()) {
+                Some(v) => &self.0.entries[*v],
+                None    => return Err(de::Error::custom(format!("Component \"{}\" isn't registered for serialization", name))),
+            };
+            result.push((entry.id, map.next_value_seed(ValueSeed(entry.deserialize))?));
+        }
+        return Ok(result);
+    }
+}
+
+struct RelationsSeed<'a>(&'a Serializers);
+
+impl<'a, 'de> DeserializeSeed<'de> for RelationsSeed<'a> {
+    type Value = Vec<LoadedRelation>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for RelationsSeed<'a> {
+    type Value = Vec<LoadedRelation>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of relation pairs keyed by ID_STR")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut result = Vec::new();
+        while let Some(name) = map.next_key::<String>()? {
+            let entry = match self.0.relations_by_name.get(name.as_str()) {
+                Some(v) => &self.0.relations[*v],
+                None    => return Err(de::Error::custom(format!("Relation \"{}\" isn't registered for serialization", name))),
+            };
+            map.next_value_seed(PairsSeed{ entry, out: &mut result })?;
+        }
+        return Ok(result);
+    }
+}
+
+/// Appends the pairs of a relation to the loaded relations
+struct PairsSeed<'a, 'b> {
+    entry: &'a RelationSerializer,
+    out: &'b mut Vec<LoadedRelation>,
+}
+
+impl<'a, 'b, 'de> DeserializeSeed<'de> for PairsSeed<'a, 'b> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'a, 'b, 'de> Visitor<'de> for PairsSeed<'a, 'b> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of source, target and value triples")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        while let Some(v) = seq.next_element_seed(PairSeed(self.entry))? {
+            self.out.push(v);
+        }
+        return Ok(());
+    }
+}
+
+struct PairSeed<'a>(&'a RelationSerializer);
+
+impl<'a, 'de> DeserializeSeed<'de> for PairSeed<'a> {
+    type Value = LoadedRelation;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_tuple(3, self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for PairSeed<'a> {
+    type Value = LoadedRelation;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a source, a target and the relation's value")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let source = seq.next_element::<EntityID>()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let target = seq.next_element::<EntityID>()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let value = seq.next_element_seed(ValueSeed(self.0.deserialize))?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        return Ok(LoadedRelation{ id: self.0.id, source, target, value });
+    }
+}
+
+/// A component or relation value, deserialized by its registered type
+struct ValueSeed(DeserializeFn);
+
+impl<'de> DeserializeSeed<'de> for ValueSeed {
+    type Value = Box<dyn Any>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+        (self.0)(&mut deserializer).map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,125 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use butterscotch_common::container::{ChunkSize, GIDStore};
+
+use crate::{Commands, ECS, EntityID, Trigger};
+
+#[repr(transparent)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct SubscriptionID(pub u32);
+
+/// Invoked with the subscribed entity and the mutation that triggered it, sees the world as it was before
+/// any of the dispatch's subscriptions are finalized
+pub type SubscriptionCallback = Box<dyn FnMut(&ECS, EntityID, Trigger, &mut Commands)>;
+
+pub struct Subscription {
+    id: SubscriptionID,
+    triggers: Vec<Trigger>,
+    callback: SubscriptionCallback,
+}
+
+impl Subscription {
+    pub fn id(&self) -> SubscriptionID {
+        self.id
+    }
+
+    pub fn triggers(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    pub(crate) fn invoke(&mut self, ecs: &ECS, eid: EntityID, trigger: Trigger, output: &mut Commands) {
+        (self.callback)(ecs, eid, trigger, output);
+    }
+}
+
+impl std::fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription").field("id", &self.id).field("triggers", &self.triggers).finish()
+    }
+}
+
+/// Callbacks watching the mutations of a single entity, keyed by the entity.
+/// Subscriptions end with their entity, those watching Despawn are kept until they've been told about it.
+#[derive(Debug)]
+pub struct Subscriptions {
+    entities: GIDStore<Vec<Subscription>>,
+    despawned: Vec<(EntityID, Vec<Subscription>)>,
+    next_id: u32,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self{
+            entities: GIDStore::new(ChunkSize::Elements(1024)),
+            despawned: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl Subscriptions {
+
+    pub fn insert(&mut self, eid: EntityID, triggers: Vec<Trigger>, callback: SubscriptionCallback) -> SubscriptionID {
+        let id = SubscriptionID(self.next_id);
+        self.next_id += 1;
+
+        let subscription = Subscription{ id, triggers, callback };
+        match self.entities.get_mut(eid) {
+            Some(v) => v.push(subscription),
+            None    => { self.entities.replace(eid, vec![subscription]); }, // Replacing drops any left by an older generation
+        }
+        return id;
+    }
+
+    pub fn remove(&mut self, eid: EntityID, id: SubscriptionID) -> bool {
+        let subscriptions = match self.entities.get_mut(eid) {
+            Some(v) => v,
+            None    => return false,
+        };
+        let len = subscriptions.len();
+        subscriptions.retain(|v| v.id != id);
+        let result = subscriptions.len() != len;
+        if subscriptions.is_empty() { self.entities.remove(eid); }
+        return result;
+    }
+
+    /// If the entity has subscriptions still waiting to be dispatched
+    pub fn contains(&self, eid: EntityID) -> bool {
+        self.entities.contains_key(eid) || self.despawned.iter().any(|v| v.0 == eid)
+    }
+
+    pub fn get(&self, eid: EntityID) -> &[Subscription] {
+        self.entities.get(eid).map_or(&[], |v| v.as_slice())
+    }
+
+    /// Drops an entity's subscriptions, keeping those watching Despawn until they've been dispatched
+    pub fn release(&mut self, eid: EntityID) {
+        let mut subscriptions = match self.entities.remove(eid) {
+            Some(v) => v,
+            None    => return,
+        };
+        subscriptions.retain(|v| v.triggers.contains(&Trigger::Despawn));
+        if !subscriptions.is_empty() { self.despawned.push((eid, subscriptions)); }
+    }
+
+    pub(crate) fn take(&mut self, eid: EntityID) -> Vec<Subscription> {
+        if let Some(v) = self.entities.remove(eid) { return v; }
+        match self.despawned.iter().position(|v| v.0 == eid) {
+            Some(i) => self.despawned.swap_remove(i).1,
+            None    => Vec::new(),
+        }
+    }
+
+    /// Drops the subscriptions still waiting for their entity's Despawn, for when the mutation is discarded
+    pub(crate) fn clear_despawned(&mut self) {
+        self.despawned.clear();
+    }
+
+    /// Puts back subscriptions taken while dispatching, plus any made in the meantime
+    pub(crate) fn restore(&mut self, eid: EntityID, mut subscriptions: Vec<Subscription>) {
+        if let Some(v) = self.entities.remove(eid) { subscriptions.extend(v); }
+        if !subscriptions.is_empty() { self.entities.insert(eid, subscriptions); }
+    }
+}
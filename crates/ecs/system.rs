@@ -0,0 +1,239 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use std::collections::HashSet;
+
+use crate::{Commands, ComponentID, ECS, EntityID, EventID, SubscriptionID, Tick};
+
+/// A mutation a reactive system can react to
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum Trigger {
+    Spawn,
+    Despawn,
+    Attach(ComponentID),
+    Detach(ComponentID),
+    Mutate(ComponentID),
+    /// Triggered for every entity attached to a sent event
+    Event(EventID),
+}
+
+#[repr(transparent)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct SystemID(pub u32);
+
+impl SystemID {
+    /// The source of mutations made by per-entity subscriptions
+    pub const SUBSCRIPTIONS: SystemID = SystemID(u32::MAX);
+
+    #[inline(always)]
+    pub fn get_idx(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Mutation {
+    pub trigger: Trigger,
+    pub eid: EntityID,
+    /// The system whose output made the mutation, None if it was made outside of a dispatch
+    pub source: Option<SystemID>,
+    /// The subscription whose output made the mutation, so it doesn't trigger itself
+    pub subscription: Option<SubscriptionID>,
+}
+
+/// Records the mutations registered systems are watching for, everything else is ignored
+#[derive(Debug, Default)]
+pub struct MutationLog {
+    watched: HashSet<Trigger>,
+    source: Option<SystemID>,
+    subscription: Option<SubscriptionID>,
+    entries: Vec<Mutation>,
+}
+
+impl MutationLog {
+
+    pub fn watch(&mut self, trigger: Trigger) {
+        self.watched.insert(trigger);
+    }
+
+    pub fn is_watched(&self, trigger: Trigger) -> bool {
+        self.watched.contains(&trigger)
+    }
+
+    pub fn record(&mut self, trigger: Trigger, eid: EntityID) {
+        if !self.is_watched(trigger) { return; }
+        self.entries.push(Mutation{ trigger, eid, source: self.source, subscription: self.subscription });
+    }
+
+    /// Attributes the following mutations to a system
+    pub fn set_source(&mut self, source: Option<SystemID>) {
+        self.source = source;
+    }
+
+    /// Attributes the following mutations to a subscription, as well as SystemID::SUBSCRIPTIONS
+    pub fn set_subscription(&mut self, subscription: Option<SubscriptionID>) {
+        self.source = subscription.map(|_| SystemID::SUBSCRIPTIONS);
+        self.subscription = subscription;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Takes every mutation recorded so far, in the order they were made
+    pub fn take(&mut self) -> Vec<Mutation> {
+        std::mem::take(&mut self.entries)
+    }
+}
+
+/// A system that is invoked for the entities whose mutations it's watching for.
+/// Rather than mutating directly, systems output commands that are applied once every invocation has run.
+pub trait ReactiveSystem: 'static {
+    /// Name used in diagnostics
+    fn name(&self) -> &'static str { std::any::type_name::<Self>() }
+
+    /// The mutations the system reacts to, queried once on registration
+    fn triggers(&self) -> Vec<Trigger>;
+
+    /// Invoked once per triggering entity, no matter how many of its mutations matched.
+    /// The entity may no longer be alive when reacting to Despawn.
+    fn react(&mut self, ecs: &ECS, eid: EntityID, output: &mut Commands);
+}
+
+struct SystemEntry {
+    triggers: Vec<Trigger>,
+    system: Option<Box<dyn ReactiveSystem>>, // Taken while the system is reacting
+    last_run: Tick,
+}
+
+/// Every registered reactive system, dispatched in registration order
+#[derive(Default)]
+pub struct Systems {
+    entries: Vec<SystemEntry>,
+}
+
+impl std::fmt::Debug for Systems {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.entries.iter().map(|v| v.system.as_ref().map(|v| v.name()))).finish()
+    }
+}
+
+impl Systems {
+
+    pub fn register<S: ReactiveSystem>(&mut self, system: S) -> SystemID {
+        let id = SystemID(self.entries.len() as u32);
+        self.entries.push(SystemEntry{ triggers: system.triggers(), system: Some(box system), last_run: Tick::default() });
+        return id;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = SystemID> {
+        (0..self.entries.len() as u32).map(SystemID)
+    }
+
+    pub fn name(&self, id: SystemID) -> &'static str {
+        if id == SystemID::SUBSCRIPTIONS { return "Subscriptions"; }
+        self.entries[id.get_idx()].system.as_ref().map_or("<reacting>", |v| v.name())
+    }
+
+    pub fn triggers(&self, id: SystemID) -> &[Trigger] {
+        &self.entries[id.get_idx()].triggers
+    }
+
+    /// The tick the system last reacted in, the default tick if it never has
+    pub fn last_run(&self, id: SystemID) -> Tick {
+        self.entries[id.get_idx()].last_run
+    }
+
+    pub(crate) fn set_last_run(&mut self, id: SystemID, tick: Tick) {
+        self.entries[id.get_idx()].last_run = tick;
+    }
+
+    /// The mutations a system should react to.
+    /// Mutations made by the system itself are skipped, systems never trigger themselves.
+    pub fn triggered_by<'a>(&'a self, id: SystemID, mutations: &'a [Mutation]) -> impl Iterator<Item = &'a Mutation> + 'a {
+        let triggers = self.triggers(id);
+        mutations.iter().filter(move |v| v.source != Some(id) && triggers.contains(&v.trigger))
+    }
+
+    /// The entities a system should react to, sorted and deduplicated
+    pub fn triggered(&self, id: SystemID, mutations: &[Mutation]) -> Vec<EntityID> {
+        let mut result = self.triggered_by(id, mutations).map(|v| v.eid).collect::<Vec<_>>();
+        result.sort_unstable();
+        result.dedup();
+        return result;
+    }
+
+    pub(crate) fn take(&mut self, id: SystemID) -> Box<dyn ReactiveSystem> {
+        self.entries[id.get_idx()].system.take().expect("System is already reacting")
+    }
+
+    pub(crate) fn restore(&mut self, id: SystemID, system: Box<dyn ReactiveSystem>) {
+        self.entries[id.get_idx()].system = Some(system);
+    }
+}
+
+/// A system triggering another during a cascade
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct CascadeEdge {
+    pub source: SystemID,
+    pub trigger: Trigger,
+    pub target: SystemID,
+}
+
+/// Systems kept triggering each other past the maximum cascade depth
+#[derive(Debug, Clone)]
+pub struct CascadeError {
+    pub depth: usize,
+    /// The systems and the mutations between them, the last edge leads back to the first when the cascade is a loop
+    pub path: Vec<CascadeEdge>,
+    /// Readable version of the path, naming systems and the ID_STR of the components and events involved
+    pub description: String,
+}
+
+impl std::fmt::Display for CascadeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Reaction cascade exceeded a depth of {}: {}", self.depth, self.description)
+    }
+}
+
+impl std::error::Error for CascadeError {}
+
+/// Finds a loop between the systems in the edges, falling back to the chain that led to one of the
+/// systems that were still reacting when the cascade is deep but doesn't loop.
+pub fn find_cascade_path(edges: &[CascadeEdge], reacting: &[SystemID]) -> Vec<CascadeEdge> {
+    fn find_loop(edges: &[CascadeEdge], node: SystemID, path: &mut Vec<CascadeEdge>) -> Option<Vec<CascadeEdge>> {
+        for edge in edges.iter().filter(|v| v.source == node && v.target != node) {
+            if let Some(start) = path.iter().position(|v| v.source == edge.target) {
+                let mut result = path[start..].to_vec();
+                result.push(*edge);
+                return Some(result);
+            }
+            path.push(*edge);
+            if let Some(v) = find_loop(edges, edge.target, path) { return Some(v); }
+            path.pop();
+        }
+        return None;
+    }
+
+    for start in reacting.iter().chain(edges.iter().map(|v| &v.source)) {
+        if let Some(v) = find_loop(edges, *start, &mut Vec::new()) { return v; }
+    }
+
+    // No loop, walk back from a system that was still reacting
+    let mut result: Vec<CascadeEdge> = Vec::new();
+    let mut node = match reacting.first() { Some(v) => *v, None => return result };
+    while let Some(edge) = edges.iter().find(|v| v.target == node && !result.iter().any(|r| r.source == v.source || r.target == v.source)) {
+        result.insert(0, *edge);
+        node = edge.source;
+    }
+    return result;
+}
@@ -0,0 +1,1457 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+
+use butterscotch_common::container::ChunkSize;
+use butterscotch_ecs_derive::{Bundle, Reflect};
+use serde::{Deserialize, Serialize};
+
+use crate::{Added, ArchetypeID, Bundle, BundleWriter, Changed, Children, Component, ComponentAccess, ComponentID, ComponentIDs, ComponentRequestTupleDefinition, DynamicComponentInfo, DynamicValue, ECS, EntityID, EntityMap, Event, EventID, FieldInfo, Mut, Or, Parent, Prefab, ReactiveSystem, Reflect, ReflectError, Relation, RelationID, Commands, ResourceAccess, ScheduledSystem, SerializeComponent, SerializeRelation, MapEntities, Scheduler, SystemContext, Trigger, With, Without, accesses_of};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Reflect)]
+struct Position(i32, i32);
+
+#[derive(Debug, PartialEq, Clone)]
+struct Velocity(i32, i32);
+
+#[derive(Debug, PartialEq)]
+struct Frozen;
+
+impl Component for Position {
+    const ID: ComponentID = ComponentID(1);
+    const ID_STR: &'static str = "Test_Position";
+}
+
+impl Component for Velocity {
+    const ID: ComponentID = ComponentID(2);
+    const ID_STR: &'static str = "Test_Velocity";
+}
+
+impl Component for Frozen {
+    const ID: ComponentID = ComponentID(3);
+    const ID_STR: &'static str = "Test_Frozen";
+}
+
+#[derive(Debug, PartialEq)]
+struct Push(i32);
+
+impl Event for Push {
+    const ID: EventID = EventID(1);
+    const ID_STR: &'static str = "Test_Push";
+}
+
+fn create_ecs() -> ECS {
+    let mut ecs = ECS::default();
+    ecs.register_component::<Position>(ChunkSize::Elements(16));
+    ecs.register_component::<Velocity>(ChunkSize::Elements(16));
+    ecs.register_component::<Frozen>(ChunkSize::Elements(16));
+    ecs.register_event::<Push>();
+    ecs
+}
+
+#[test]
+fn test_lifecycle() {
+    let mut ecs = create_ecs();
+
+    // // Spawn & Attach // //
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    assert!(ecs.is_alive(a));
+    assert_eq!(ecs.attach(a, Position(1, 1)), None);
+    assert_eq!(ecs.attach(a, Velocity(2, 2)), None);
+    assert_eq!(ecs.attach(b, Position(3, 3)), None);
+    assert_eq!(ecs.attach(a, Position(4, 4)), Some(Position(1, 1)));
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(4, 4)));
+    assert_eq!(ecs.get_ref::<Velocity>(a), Some(&Velocity(2, 2)));
+    assert_eq!(ecs.get_ref::<Position>(b), Some(&Position(3, 3)));
+
+    // // Detach // //
+    assert_eq!(ecs.detach::<Velocity>(a), Some(Velocity(2, 2)));
+    assert_eq!(ecs.detach::<Velocity>(a), None);
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(4, 4)));
+    assert_eq!(ecs.archetypes().location(a).unwrap().archetype, ecs.archetypes().location(b).unwrap().archetype);
+
+    // // Despawn // //
+    assert!(ecs.despawn(a));
+    assert!(!ecs.despawn(a));
+    assert!(!ecs.is_alive(a));
+    assert_eq!(ecs.get_ref::<Position>(a), None);
+    assert_eq!(ecs.get_ref::<Position>(b), Some(&Position(3, 3)));
+
+    // // Stale IDs don't alias reused slots // //
+    let c = (0..1024).map(|_| ecs.spawn()).find(|v| v.get_idx() == a.get_idx()).unwrap();
+    assert!(ecs.is_alive(c));
+    assert!(!ecs.is_alive(a));
+    assert_eq!(ecs.get_ref::<Position>(a), None);
+    assert_eq!(ecs.archetypes().location(c).unwrap().archetype, ArchetypeID::EMPTY);
+}
+
+#[test]
+fn test_mutation() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.attach(a, Position(1, 1));
+    ecs.attach(b, Position(2, 2));
+    ecs.attach(b, Velocity(1, 1));
+
+    // // Single Entity // //
+    ecs.get_mut::<Position>(a).unwrap().0 = 5;
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(5, 1)));
+    assert!(ecs.get_mut::<Velocity>(a).is_none());
+    assert!(ecs.has::<Velocity>(b));
+
+    // // Whole Store // //
+    for v in ecs.get_store_mut::<Position>().iter_mut() { v.1 += 10; }
+    assert_eq!(ecs.get_store_ref::<Position>().len(), 2);
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(5, 11)));
+    assert_eq!(ecs.get_ref::<Position>(b), Some(&Position(2, 12)));
+
+    let mut entities = Vec::new();
+    ecs.entities_with::<Position>(&mut entities);
+    let values = ecs.get_store_ref::<Position>().iter().collect::<Vec<_>>();
+    assert_eq!(entities.len(), 2);
+    for (eid, value) in entities.iter().zip(values) {
+        assert_eq!(ecs.get_ref::<Position>(*eid), Some(value));
+    }
+}
+
+#[test]
+fn test_query() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.attach(a, Position(1, 1));
+    ecs.attach(a, Velocity(1, 1));
+    ecs.attach(b, Position(2, 2));
+
+    // // Registration catches up with existing entities // //
+    ecs.register_query::<((Velocity, Position), ())>();
+    ecs.register_query::<((Position,), (Velocity,))>();
+    let matched = ecs.query::<((Velocity, Position), ())>().map(|(eid, _, _)| eid).collect::<Vec<_>>();
+    assert_eq!(matched, vec![a]);
+    assert_eq!(ecs.query::<((Position,), (Velocity,))>().count(), 2);
+
+    // // Attach & Detach keep the query in sync // //
+    ecs.attach(b, Velocity(3, 3));
+    ecs.detach::<Position>(a);
+    let matched = ecs.query::<((Velocity, Position), ())>().collect::<Vec<_>>();
+    assert_eq!(matched, vec![(b, (&Velocity(3, 3), &Position(2, 2)), ())]);
+
+    // // Despawn // //
+    ecs.despawn(b);
+    assert_eq!(ecs.query::<((Velocity, Position), ())>().count(), 0);
+    assert_eq!(ecs.query::<((Position,), (Velocity,))>().count(), 0);
+}
+
+#[test]
+fn test_query_mut() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.attach(a, Position(0, 0));
+    ecs.attach(a, Velocity(1, 2));
+    ecs.attach(b, Position(5, 5));
+
+    ecs.register_query_mut::<((Mut<Position>,), (Velocity,))>();
+    for (_, (position,), (velocity,)) in ecs.query_mut::<((Mut<Position>,), (Velocity,))>() {
+        let velocity = velocity.unwrap_or(&Velocity(0, 0));
+        position.0 += velocity.0;
+        position.1 += velocity.1;
+    }
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(1, 2)));
+    assert_eq!(ecs.get_ref::<Position>(b), Some(&Position(5, 5)));
+}
+
+#[test]
+#[should_panic(expected = "requested more than once")]
+fn test_query_mut_aliasing() {
+    let mut ecs = create_ecs();
+    ecs.register_query_mut::<((Mut<Position>,), (Position,))>();
+    ecs.query_mut::<((Mut<Position>,), (Position,))>().count();
+}
+
+#[test]
+fn test_query_filter() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    let c = ecs.spawn();
+    ecs.attach(a, Position(0, 0));
+    ecs.attach(b, Position(0, 0));
+    ecs.attach(b, Frozen);
+    ecs.attach(c, Velocity(0, 0));
+
+    ecs.register_query::<((Position,), (), (Without<Frozen>,))>();
+    ecs.register_query::<((), (), (With<Frozen>,))>();
+    ecs.register_query::<((), (), (Or<(Position, Velocity)>, Without<Frozen>))>();
+
+    let sorted = |mut v: Vec<_>| { v.sort(); v };
+    assert_eq!(ecs.query::<((Position,), (), (Without<Frozen>,))>().map(|v| v.0).collect::<Vec<_>>(), vec![a]);
+    assert_eq!(ecs.query::<((), (), (With<Frozen>,))>().map(|v| v.0).collect::<Vec<_>>(), vec![b]);
+    assert_eq!(sorted(ecs.query::<((), (), (Or<(Position, Velocity)>, Without<Frozen>))>().map(|v| v.0).collect()), vec![a, c]);
+
+    // // Exclusion follows attach & detach // //
+    ecs.attach(a, Frozen);
+    ecs.detach::<Frozen>(b);
+    assert_eq!(ecs.query::<((Position,), (), (Without<Frozen>,))>().map(|v| v.0).collect::<Vec<_>>(), vec![b]);
+    assert_eq!(sorted(ecs.query::<((), (), (Or<(Position, Velocity)>, Without<Frozen>))>().map(|v| v.0).collect()), vec![b, c]);
+
+    ecs.detach::<Velocity>(c);
+    assert_eq!(ecs.query::<((), (), (Or<(Position, Velocity)>, Without<Frozen>))>().map(|v| v.0).collect::<Vec<_>>(), vec![b]);
+}
+
+#[test]
+fn test_change_detection() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.attach(a, Position(0, 0));
+    ecs.attach(b, Position(0, 0));
+    ecs.register_query::<((), (), (Added<Position>,))>();
+    ecs.register_query::<((), (), (Changed<Position>,))>();
+    ecs.register_query_mut::<((Mut<Position>,), (), ())>();
+
+    let sorted = |mut v: Vec<_>| { v.sort(); v };
+    assert_eq!(sorted(ecs.query::<((), (), (Added<Position>,))>().map(|v| v.0).collect()), vec![a, b]);
+
+    // // Only changes after the last tick are seen // //
+    let last = ecs.advance_tick();
+    assert_eq!(ecs.query_since::<((), (), (Added<Position>,))>(last).count(), 0);
+    assert_eq!(ecs.query_since::<((), (), (Changed<Position>,))>(last).count(), 0);
+
+    ecs.get_mut::<Position>(b).unwrap().0 = 1;
+    assert_eq!(ecs.query_since::<((), (), (Added<Position>,))>(last).count(), 0);
+    assert_eq!(ecs.query_since::<((), (), (Changed<Position>,))>(last).map(|v| v.0).collect::<Vec<_>>(), vec![b]);
+
+    // // Mutable queries mark as changed, ticks survive archetype moves // //
+    let last = ecs.advance_tick();
+    ecs.query_mut::<((Mut<Position>,), (), ())>().for_each(|(_, (p,), _)| p.1 = 2);
+    ecs.attach(a, Frozen);
+    assert_eq!(sorted(ecs.query_since::<((), (), (Changed<Position>,))>(last).map(|v| v.0).collect()), vec![a, b]);
+    assert_eq!(ecs.get_ticks::<Position>(a).unwrap().added, crate::Tick(1));
+}
+
+/// Counts the positions changed since it last reacted
+struct CountMoved;
+
+impl ReactiveSystem for CountMoved {
+    fn triggers(&self) -> Vec<Trigger> {
+        vec![Trigger::Attach(Frozen::ID)]
+    }
+
+    fn react(&mut self, ecs: &ECS, eid: EntityID, output: &mut Commands) {
+        let moved = ecs.query::<((), (), (Changed<Position>,))>().count();
+        output.attach(eid, Velocity(moved as i32, 0));
+    }
+}
+
+#[test]
+fn test_reactive_last_run() {
+    let mut ecs = create_ecs();
+    ecs.register_system(CountMoved);
+    ecs.register_query::<((), (), (Changed<Position>,))>();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.attach(a, Position(0, 0));
+    ecs.attach(b, Position(0, 0));
+
+    // Sees everything the first time it reacts
+    ecs.advance_tick();
+    ecs.attach(a, Frozen);
+    ecs.dispatch();
+    assert_eq!(ecs.get_ref::<Velocity>(a), Some(&Velocity(2, 0)));
+    assert_eq!(ecs.last_run(), crate::Tick::default());
+
+    // Then only what changed since
+    ecs.detach::<Frozen>(a);
+    ecs.advance_tick();
+    ecs.get_mut::<Position>(b).unwrap().0 = 1;
+    ecs.attach(a, Frozen);
+    ecs.dispatch();
+    assert_eq!(ecs.get_ref::<Velocity>(a), Some(&Velocity(1, 0)));
+    assert_eq!(ecs.query::<((), (), (Changed<Position>,))>().count(), 2); // Outside of dispatch
+}
+
+/// Moves and freezes another entity once the first gets a velocity
+struct Spread(EntityID);
+
+impl ReactiveSystem for Spread {
+    fn triggers(&self) -> Vec<Trigger> {
+        vec![Trigger::Attach(Velocity::ID)]
+    }
+
+    fn react(&mut self, _ecs: &ECS, _eid: EntityID, output: &mut Commands) {
+        output.set(self.0, Position(1, 1));
+        output.attach(self.0, Frozen);
+    }
+}
+
+#[test]
+fn test_reactive_last_run_cascade() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.register_system(CountMoved);
+    ecs.register_system(Spread(b));
+    ecs.register_query::<((), (), (Changed<Position>,))>();
+    ecs.attach(a, Position(0, 0));
+    ecs.attach(b, Position(0, 0));
+
+    // CountMoved reacts to a, Spread moves b, then CountMoved reacts to b within the same tick
+    ecs.attach(a, Frozen);
+    ecs.cascade().unwrap();
+    assert_eq!(ecs.get_ref::<Velocity>(a), Some(&Velocity(2, 0)));
+    assert_eq!(ecs.get_ref::<Velocity>(b), Some(&Velocity(1, 0)));
+}
+
+#[test]
+fn test_commands() {
+    let mut ecs = create_ecs();
+    ecs.register_query::<((Position,), (), (Without<Frozen>,))>();
+    let a = ecs.spawn();
+    ecs.attach(a, Position(0, 0));
+
+    // // Nothing is visible until applied // //
+    let mut commands = Commands::new();
+    let b = commands.spawn();
+    commands.attach(b, Position(1, 1));
+    commands.set(b, Position(2, 2));
+    commands.set(a, Velocity(0, 0)); // Skipped, a doesn't have one
+    commands.attach(a, Frozen);
+    commands.detach::<Frozen, _>(a);
+
+    let mut more = Commands::new();
+    let c = more.spawn();
+    more.attach(c, Position(3, 3));
+    more.despawn(c);
+    commands.append(more);
+    assert_eq!(commands.len(), 9);
+    assert_eq!(ecs.query::<((Position,), (), (Without<Frozen>,))>().count(), 1);
+
+    let spawned = ecs.apply(commands);
+    assert_eq!(spawned.len(), 2);
+    assert!(ecs.is_alive(spawned[0]));
+    assert!(!ecs.is_alive(spawned[1]));
+    assert_eq!(ecs.get_ref::<Position>(spawned[0]), Some(&Position(2, 2)));
+    assert!(!ecs.has::<Velocity>(a) && !ecs.has::<Frozen>(a));
+
+    let sorted = |mut v: Vec<_>| { v.sort(); v };
+    assert_eq!(sorted(ecs.query::<((Position,), (), (Without<Frozen>,))>().map(|v| v.0).collect()), vec![a, spawned[0]]);
+
+    // // Queries see the net result of a batch // //
+    let mut commands = Commands::new();
+    commands.attach(a, Frozen);
+    commands.despawn(spawned[0]);
+    let d = commands.spawn();
+    commands.attach(d, Position(4, 4));
+    let spawned = ecs.apply(commands);
+    assert_eq!(ecs.query::<((Position,), (), (Without<Frozen>,))>().map(|v| v.0).collect::<Vec<_>>(), spawned);
+}
+
+#[test]
+#[should_panic(expected = "taken from another command buffer")]
+fn test_commands_foreign_target() {
+    let mut ecs = create_ecs();
+    let mut other = Commands::new();
+    other.spawn();
+    let target = other.spawn();
+
+    let mut commands = Commands::new();
+    commands.attach(target, Position(0, 0));
+    ecs.apply(commands);
+}
+
+/// Freezes anything that gets a position, no invocation should see another's output
+struct FreezeOnAttach;
+
+impl ReactiveSystem for FreezeOnAttach {
+    fn triggers(&self) -> Vec<Trigger> {
+        vec![Trigger::Attach(Position::ID)]
+    }
+
+    fn react(&mut self, ecs: &ECS, eid: EntityID, output: &mut Commands) {
+        let mut frozen = Vec::new();
+        ecs.entities_with::<Frozen>(&mut frozen);
+        assert!(frozen.is_empty());
+        output.attach(eid, Frozen);
+    }
+}
+
+/// Nudges the position of anything that gets frozen or moved
+struct Nudge;
+
+impl ReactiveSystem for Nudge {
+    fn triggers(&self) -> Vec<Trigger> {
+        vec![Trigger::Attach(Frozen::ID), Trigger::Mutate(Position::ID)]
+    }
+
+    fn react(&mut self, ecs: &ECS, eid: EntityID, output: &mut Commands) {
+        let position = ecs.get_ref::<Position>(eid).unwrap();
+        output.set(eid, Position(position.0 + 1, position.1));
+    }
+}
+
+#[test]
+fn test_reactive_system() {
+    let mut ecs = create_ecs();
+    ecs.register_system(FreezeOnAttach);
+    ecs.register_system(Nudge);
+
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.attach(a, Position(0, 0));
+    ecs.attach(b, Position(0, 0));
+
+    // // Reactions cascade into the next dispatch // //
+    assert_eq!(ecs.dispatch(), 2);
+    assert!(ecs.has::<Frozen>(a) && ecs.has::<Frozen>(b));
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(0, 0)));
+
+    assert_eq!(ecs.dispatch(), 2);
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(1, 0)));
+
+    // // Systems don't trigger themselves // //
+    assert_eq!(ecs.dispatch(), 0);
+
+    ecs.get_mut::<Position>(b).unwrap().1 = 5;
+    assert_eq!(ecs.dispatch(), 1);
+    assert_eq!(ecs.get_ref::<Position>(b), Some(&Position(2, 5)));
+    assert_eq!(ecs.dispatch(), 0);
+}
+
+/// Pushes the entities an event was sent for
+struct ApplyPush;
+
+impl ReactiveSystem for ApplyPush {
+    fn triggers(&self) -> Vec<Trigger> {
+        vec![Trigger::Event(Push::ID)]
+    }
+
+    fn react(&mut self, ecs: &ECS, eid: EntityID, output: &mut Commands) {
+        let push: i32 = ecs.events::<Push>().iter().filter(|v| v.entities.contains(&eid)).map(|v| v.data.0).sum();
+        let position = ecs.get_ref::<Position>(eid).unwrap();
+        output.set(eid, Position(position.0 + push, position.1));
+    }
+}
+
+#[test]
+fn test_events() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+
+    // // Readers have their own cursors // //
+    let mut early = ecs.event_reader::<Push>();
+    ecs.send(&[a], Push(1));
+    let mut late = ecs.event_reader::<Push>();
+    ecs.send(&[a, b], Push(2));
+    assert_eq!(ecs.read(&mut early).map(|v| v.data.0).collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(ecs.read(&mut late).map(|v| v.data.0).collect::<Vec<_>>(), vec![2]);
+    assert_eq!(ecs.read(&mut early).count(), 0);
+    assert_eq!(ecs.read(&mut late).next().map(|v| v.entities.clone()), None);
+
+    // // Events live for two ticks // //
+    let mut reader = ecs.events::<Push>().reader_from_start();
+    ecs.advance_tick();
+    ecs.send(&[], Push(3));
+    assert_eq!(ecs.events::<Push>().len(), 3);
+    ecs.advance_tick();
+    assert_eq!(ecs.read(&mut reader).map(|v| v.data.0).collect::<Vec<_>>(), vec![3]);
+    ecs.advance_tick();
+    assert!(ecs.events::<Push>().is_empty());
+
+    // // Systems react to the entities attached to events // //
+    ecs.register_system(ApplyPush);
+    ecs.attach(a, Position(0, 0));
+    ecs.attach(b, Position(0, 0));
+    ecs.send(&[a], Push(5));
+    let mut commands = Commands::new();
+    commands.send(vec![a, b], Push(1));
+    ecs.apply(commands);
+
+    assert_eq!(ecs.dispatch(), 2);
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(6, 0)));
+    assert_eq!(ecs.get_ref::<Position>(b), Some(&Position(1, 0)));
+}
+
+/// Copies position into velocity, and velocity back into position, forever
+struct Mirror(bool);
+
+impl ReactiveSystem for Mirror {
+    fn name(&self) -> &'static str {
+        if self.0 { "MirrorPosition" } else { "MirrorVelocity" }
+    }
+
+    fn triggers(&self) -> Vec<Trigger> {
+        match self.0 {
+            true  => vec![Trigger::Mutate(Position::ID)],
+            false => vec![Trigger::Mutate(Velocity::ID)],
+        }
+    }
+
+    fn react(&mut self, ecs: &ECS, eid: EntityID, output: &mut Commands) {
+        match self.0 {
+            true  => { let v = ecs.get_ref::<Position>(eid).unwrap(); output.set(eid, Velocity(v.0, v.1)); },
+            false => { let v = ecs.get_ref::<Velocity>(eid).unwrap(); output.set(eid, Position(v.0 + 1, v.1)); },
+        }
+    }
+}
+
+#[test]
+fn test_cascade() {
+    let mut ecs = create_ecs();
+    ecs.register_system(FreezeOnAttach);
+    ecs.register_system(Nudge);
+    let a = ecs.spawn();
+    ecs.attach(a, Position(0, 0));
+    assert_eq!(ecs.cascade().unwrap(), 2);
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(1, 0)));
+
+    // // Loops are reported rather than spinning // //
+    let mut ecs = create_ecs();
+    ecs.set_max_cascade_depth(8);
+    let position = ecs.register_system(Mirror(true));
+    let velocity = ecs.register_system(Mirror(false));
+    let a = ecs.spawn();
+    ecs.attach(a, Position(0, 0));
+    ecs.attach(a, Velocity(0, 0));
+    ecs.get_mut::<Position>(a);
+
+    let error = ecs.cascade().unwrap_err();
+    assert_eq!(error.path.len(), 2);
+    assert!(error.path.iter().any(|v| v.source == position && v.target == velocity && v.trigger == Trigger::Mutate(Velocity::ID)));
+    assert!(error.path.iter().any(|v| v.source == velocity && v.target == position && v.trigger == Trigger::Mutate(Position::ID)));
+    assert!(error.to_string().contains("MirrorPosition -[Mutate(Test_Velocity)]-> MirrorVelocity"), "{}", error);
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(4, 0)));
+
+    // // Remaining mutations are discarded // //
+    assert_eq!(ecs.cascade().unwrap(), 0);
+
+    let b = ecs.spawn();
+    ecs.subscribe(b, vec![Trigger::Despawn], |_, _, _, _| panic!("Discarded Despawn was dispatched"));
+    ecs.despawn(b);
+    ecs.set_max_cascade_depth(0);
+    assert!(ecs.cascade().is_err());
+    assert!(!ecs.subscriptions().contains(b));
+}
+
+/// Adds velocity to position
+struct Move;
+
+impl ScheduledSystem for Move {
+    fn accesses(&self) -> Vec<ComponentAccess> {
+        accesses_of::<((Mut<Position>, Velocity), ())>()
+    }
+
+    fn run(&mut self, context: &mut SystemContext) {
+        for (_, (position, velocity), _) in context.query_mut::<((Mut<Position>, Velocity), ())>() {
+            position.0 += velocity.0;
+            position.1 += velocity.1;
+        }
+    }
+}
+
+/// Halves velocity
+struct Damp;
+
+impl ScheduledSystem for Damp {
+    fn accesses(&self) -> Vec<ComponentAccess> {
+        accesses_of::<((Mut<Velocity>,), ())>()
+    }
+
+    fn run(&mut self, context: &mut SystemContext) {
+        for (_, (velocity,), _) in context.query_mut::<((Mut<Velocity>,), ())>() {
+            velocity.0 /= 2;
+            velocity.1 /= 2;
+        }
+    }
+}
+
+/// Freezes anything that has stopped, reading Velocity without declaring it when asked to misbehave
+struct Freeze(bool);
+
+impl ScheduledSystem for Freeze {
+    fn accesses(&self) -> Vec<ComponentAccess> {
+        match self.0 {
+            true  => accesses_of::<((Frozen,), ())>(),
+            false => accesses_of::<((Velocity,), ())>(),
+        }
+    }
+
+    fn run(&mut self, context: &mut SystemContext) {
+        let stopped = context.query::<((Velocity,), ())>().filter(|v| (v.1).0 == &Velocity(0, 0)).map(|v| v.0).collect::<Vec<_>>();
+        for eid in stopped {
+            context.commands().attach(eid, Frozen);
+        }
+    }
+}
+
+#[test]
+fn test_scheduler() {
+    let run = |threads: usize| {
+        let mut ecs = create_ecs();
+        ecs.register_query::<((Position, Velocity), ())>();
+        ecs.register_query::<((Velocity,), ())>();
+        let entities = (0..64).map(|i| {
+            let eid = ecs.spawn();
+            ecs.attach(eid, Position(0, 0));
+            ecs.attach(eid, Velocity(i, 1));
+            eid
+        }).collect::<Vec<_>>();
+
+        let mut scheduler = Scheduler::with_threads(threads);
+        scheduler.add_system(Move);
+        scheduler.add_system(Freeze(false));
+        scheduler.add_system(Damp);
+        assert_eq!(scheduler.stages(), vec![vec![std::any::type_name::<Move>(), std::any::type_name::<Freeze>()], vec![std::any::type_name::<Damp>()]]);
+
+        for _ in 0..4 {
+            scheduler.run(&mut ecs);
+            ecs.advance_tick();
+        }
+        entities.iter().map(|v| (ecs.get_ref::<Position>(*v).unwrap().0, ecs.get_ref::<Position>(*v).unwrap().1, ecs.has::<Frozen>(*v))).collect::<Vec<_>>()
+    };
+
+    let single = run(1);
+    assert_eq!(single[0], (0, 1, true));
+    assert_eq!(single[63], (63 + 31 + 15 + 7, 1, false));
+    assert_eq!(run(4), single);
+}
+
+#[test]
+#[should_panic(expected = "didn't declare immutable access")]
+fn test_scheduler_undeclared() {
+    let mut ecs = create_ecs();
+    ecs.register_query::<((Velocity,), ())>();
+    let mut scheduler = Scheduler::with_threads(1);
+    scheduler.add_system(Freeze(true));
+    scheduler.run(&mut ecs);
+}
+
+/// Visits the moving entities whose position changed, declaring the filter only when asked to behave
+struct VisitMoved(bool);
+
+impl ScheduledSystem for VisitMoved {
+    fn accesses(&self) -> Vec<ComponentAccess> {
+        match self.0 {
+            true  => accesses_of::<((Velocity,), (), (Changed<Position>,))>(),
+            false => accesses_of::<((Velocity,), ())>(),
+        }
+    }
+
+    fn run(&mut self, context: &mut SystemContext) {
+        let since = context.last_run();
+        for (eid, (velocity,), _) in context.query_since::<((Velocity,), (), (Changed<Position>,))>(since) {
+            assert!(context.get_ref::<Velocity>(eid) == Some(velocity));
+        }
+    }
+}
+
+#[test]
+fn test_scheduler_filters() {
+    assert!(accesses_of::<((Velocity,), (), (Changed<Position>,))>().contains(&ComponentAccess{ id: Position::ID, id_str: Position::ID_STR, mutable: false }));
+
+    // // Filter components conflict with writers // //
+    let mut scheduler = Scheduler::with_threads(1);
+    scheduler.add_system(Move);
+    scheduler.add_system(VisitMoved(true));
+    assert_eq!(scheduler.stages(), vec![vec![std::any::type_name::<Move>()], vec![std::any::type_name::<VisitMoved>()]]);
+}
+
+#[test]
+#[should_panic(expected = "didn't declare immutable access")]
+fn test_scheduler_undeclared_filter() {
+    let mut ecs = create_ecs();
+    ecs.register_query::<((Velocity,), (), (Changed<Position>,))>();
+    let mut scheduler = Scheduler::with_threads(1);
+    scheduler.add_system(VisitMoved(false));
+    scheduler.run(&mut ecs);
+}
+
+#[test]
+fn test_subscriptions() {
+    use std::{cell::RefCell, rc::Rc};
+
+    let mut ecs = create_ecs();
+    let player = ecs.spawn();
+    let other = ecs.spawn();
+    ecs.attach(player, Position(0, 0));
+    ecs.attach(other, Position(0, 0));
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let log = seen.clone();
+    ecs.subscribe(player, vec![Trigger::Mutate(Position::ID), Trigger::Despawn], move |ecs, eid, trigger, commands| {
+        log.borrow_mut().push(trigger);
+        if let Some(v) = ecs.get_ref::<Position>(eid) { commands.set(eid, Position(v.0, 10)); }
+    });
+    let counter = ecs.subscribe(player, vec![Trigger::Attach(Velocity::ID)], |_, _, _, _| {});
+
+    // // Only the subscribed entity triggers, and the callback doesn't trigger itself // //
+    ecs.get_mut::<Position>(other).unwrap().0 = 1;
+    ecs.get_mut::<Position>(player).unwrap().0 = 2;
+    assert_eq!(ecs.dispatch(), 1);
+    assert_eq!(ecs.get_ref::<Position>(player), Some(&Position(2, 10)));
+    assert_eq!(ecs.dispatch(), 0);
+
+    assert!(ecs.unsubscribe(player, counter));
+    assert!(!ecs.unsubscribe(player, counter));
+    assert_eq!(ecs.subscriptions().get(player).len(), 1);
+
+    // // Despawning tells the subscription once, then drops it // //
+    ecs.despawn(player);
+    assert!(ecs.subscriptions().get(player).is_empty());
+    assert_eq!(ecs.dispatch(), 1);
+    assert_eq!(*seen.borrow(), vec![Trigger::Mutate(Position::ID), Trigger::Despawn]);
+    assert!(!ecs.subscriptions().contains(player));
+
+    // // Reused slots don't inherit subscriptions // //
+    let reused = (0..1024).map(|_| ecs.spawn()).find(|v| v.get_idx() == player.get_idx()).unwrap();
+    ecs.attach(reused, Position(0, 0));
+    ecs.get_mut::<Position>(reused);
+    assert_eq!(ecs.dispatch(), 0);
+}
+
+#[test]
+fn test_subscriptions_cascade() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.attach(a, Position(0, 0));
+    ecs.attach(b, Position(0, 0));
+
+    // // One entity's callback triggers another's // //
+    ecs.subscribe(a, vec![Trigger::Mutate(Position::ID)], move |ecs, eid, _, commands| {
+        let v = ecs.get_ref::<Position>(eid).unwrap();
+        commands.set(b, Position(v.0, 0));
+    });
+    ecs.subscribe(b, vec![Trigger::Mutate(Position::ID)], |ecs, eid, _, commands| {
+        let v = ecs.get_ref::<Position>(eid).unwrap();
+        commands.attach(eid, Velocity(v.0, 0));
+    });
+    ecs.get_mut::<Position>(a).unwrap().0 = 3;
+    assert_eq!(ecs.cascade().unwrap(), 2);
+    assert_eq!(ecs.get_ref::<Position>(b), Some(&Position(3, 0)));
+    assert_eq!(ecs.get_ref::<Velocity>(b), Some(&Velocity(3, 0)));
+
+    // // Subscriptions triggering each other are cut off by the depth limit // //
+    ecs.set_max_cascade_depth(8);
+    ecs.subscribe(b, vec![Trigger::Mutate(Position::ID)], move |ecs, eid, _, commands| {
+        let v = ecs.get_ref::<Position>(eid).unwrap();
+        commands.set(a, Position(v.0 + 1, 0));
+    });
+    ecs.get_mut::<Position>(a);
+    let error = ecs.cascade().unwrap_err();
+    assert!(error.to_string().contains("Subscriptions"), "{}", error);
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(7, 0)));
+    assert_eq!(ecs.cascade().unwrap(), 0);
+}
+
+#[derive(Debug, PartialEq)]
+struct Gravity(i32);
+
+#[derive(Debug, Default, PartialEq)]
+struct Moved(usize);
+
+/// Counts the entities with a velocity into Moved, only declaring a read when asked to misbehave
+struct Count(bool);
+
+impl ScheduledSystem for Count {
+    fn accesses(&self) -> Vec<ComponentAccess> {
+        accesses_of::<((Velocity,), ())>()
+    }
+
+    fn resource_accesses(&self) -> Vec<ResourceAccess> {
+        match self.0 {
+            true  => vec![ResourceAccess::read::<Moved>()],
+            false => vec![ResourceAccess::write::<Moved>()],
+        }
+    }
+
+    fn run(&mut self, context: &mut SystemContext) {
+        let count = context.query::<((Velocity,), ())>().count();
+        context.resource_mut::<Moved>().unwrap().0 += count;
+    }
+}
+
+/// Pulls velocities down by Gravity
+struct Fall;
+
+impl ScheduledSystem for Fall {
+    fn accesses(&self) -> Vec<ComponentAccess> {
+        accesses_of::<((Mut<Velocity>,), ())>()
+    }
+
+    fn resource_accesses(&self) -> Vec<ResourceAccess> {
+        vec![ResourceAccess::read::<Gravity>()]
+    }
+
+    fn run(&mut self, context: &mut SystemContext) {
+        let gravity = context.resource::<Gravity>().unwrap().0;
+        for (_, (velocity,), _) in context.query_mut::<((Mut<Velocity>,), ())>() {
+            velocity.1 -= gravity;
+        }
+        context.commands().insert_resource(Gravity(gravity + 1));
+    }
+}
+
+#[test]
+fn test_resources() {
+    let mut ecs = create_ecs();
+    ecs.register_query::<((Velocity,), ())>();
+
+    // // Direct access // //
+    assert_eq!(ecs.insert_resource(Gravity(1)), None);
+    assert_eq!(ecs.insert_resource(Gravity(2)), Some(Gravity(1)));
+    ecs.resource_mut::<Gravity>().unwrap().0 -= 1;
+    assert_eq!(ecs.resource::<Gravity>(), Some(&Gravity(1)));
+    assert_eq!(ecs.resource::<Moved>(), None);
+
+    // // Commands // //
+    let mut commands = Commands::new();
+    commands.insert_resource(Moved(0));
+    commands.remove_resource::<Gravity>();
+    ecs.apply(commands);
+    assert!(!ecs.has_resource::<Gravity>());
+    assert_eq!(ecs.remove_resource::<Moved>(), Some(Moved(0)));
+    assert!(ecs.resources().is_empty());
+
+    // // Scheduling // //
+    ecs.insert_resource(Gravity(1));
+    ecs.insert_resource(Moved(0));
+    let eid = ecs.spawn();
+    ecs.attach(eid, Velocity(0, 0));
+
+    let mut scheduler = Scheduler::with_threads(2);
+    scheduler.add_system(Count(false));
+    scheduler.add_system(Freeze(false));
+    scheduler.add_system(Count(false));
+    scheduler.add_system(Fall);
+    assert_eq!(scheduler.stages(), vec![
+        vec![std::any::type_name::<Count>(), std::any::type_name::<Freeze>()],
+        vec![std::any::type_name::<Count>()],
+        vec![std::any::type_name::<Fall>()],
+    ]);
+
+    scheduler.run(&mut ecs);
+    assert_eq!(ecs.resource::<Moved>(), Some(&Moved(2)));
+    assert_eq!(ecs.resource::<Gravity>(), Some(&Gravity(2)));
+    assert_eq!(ecs.get_ref::<Velocity>(eid), Some(&Velocity(0, -1)));
+}
+
+#[test]
+#[should_panic(expected = "didn't declare mutable access to resource")]
+fn test_resources_undeclared() {
+    let mut ecs = create_ecs();
+    ecs.insert_resource(Moved(0));
+    ecs.register_query::<((Velocity,), ())>();
+
+    let mut scheduler = Scheduler::with_threads(1);
+    scheduler.add_system(Count(true));
+    scheduler.run(&mut ecs);
+}
+
+#[test]
+fn test_hierarchy() {
+    let mut ecs = create_ecs();
+    ecs.register_query::<((Parent,), ())>();
+    ecs.register_query::<((Children,), ())>();
+    let count = |ecs: &ECS| (ecs.query::<((Parent,), ())>().count(), ecs.query::<((Children,), ())>().count());
+
+    // // Building // //
+    let root = ecs.spawn();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    let c = ecs.spawn();
+    ecs.set_parent(a, root);
+    ecs.set_parent(b, root);
+    ecs.set_parent(c, a);
+    assert_eq!(ecs.children(root), &[a, b]);
+    assert_eq!(ecs.parent(c), Some(a));
+    assert_eq!(ecs.ancestors(c).collect::<Vec<_>>(), vec![a, root]);
+    assert_eq!(ecs.traverse(root).collect::<Vec<_>>(), vec![(root, 0), (a, 1), (c, 2), (b, 1)]);
+    assert_eq!(count(&ecs), (3, 2));
+
+    // // Re-parenting // //
+    ecs.set_parent(c, b);
+    assert_eq!(ecs.children(a), &[]);
+    assert!(!ecs.has::<Children>(a));
+    assert_eq!(ecs.get_ref::<Children>(b).map(|v| v.as_slice()), Some(&[c][..]));
+    assert_eq!(ecs.remove_parent(b), Some(root));
+    assert_eq!(ecs.remove_parent(b), None);
+    assert_eq!(ecs.descendants(root).collect::<Vec<_>>(), vec![a]);
+    assert_eq!(count(&ecs), (2, 2));
+
+    // // Commands // //
+    let mut commands = Commands::new();
+    let d = commands.spawn();
+    commands.set_parent(d, c);
+    commands.set_parent(b, c); // Cycle, skipped
+    commands.set_parent(b, root);
+    commands.remove_parent(a);
+    let d = ecs.apply(commands)[0];
+    assert_eq!(ecs.traverse(root).collect::<Vec<_>>(), vec![(root, 0), (b, 1), (c, 2), (d, 3)]);
+    assert_eq!(ecs.parent(a), None);
+    assert_eq!(count(&ecs), (3, 3));
+
+    // // Cascading despawn // //
+    let mut commands = Commands::new();
+    commands.attach(c, Position(0, 0));
+    commands.despawn(b);
+    ecs.apply(commands);
+    for eid in [b, c, d].iter() {
+        assert!(!ecs.is_alive(*eid));
+    }
+    assert_eq!(ecs.children(root), &[]);
+    assert_eq!(count(&ecs), (0, 0));
+
+    // // Stale IDs // //
+    let reused = (0..1024).map(|_| ecs.spawn()).find(|v| v.get_idx() == c.get_idx()).unwrap();
+    ecs.set_parent(reused, a);
+    assert_eq!(ecs.parent(c), None);
+    assert_eq!(ecs.traverse(c).count(), 0);
+    assert!(ecs.despawn(a));
+    assert!(!ecs.is_alive(reused));
+    assert_eq!(count(&ecs), (0, 0));
+}
+
+#[test]
+#[should_panic(expected = "one of its descendants")]
+fn test_hierarchy_cycle() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.set_parent(b, a);
+    ecs.set_parent(a, b);
+}
+
+#[test]
+#[should_panic(expected = "Parent and Children can only be changed through set_parent and remove_parent")]
+fn test_hierarchy_detach() {
+    let mut ecs = create_ecs();
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    ecs.set_parent(b, a);
+    assert!(!ecs.get_ref::<Children>(a).unwrap().is_empty());
+    ecs.detach::<Parent>(b);
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Targets(u32);
+
+impl Relation for Targets {
+    const ID: RelationID = RelationID(1);
+    const ID_STR: &'static str = "Test_Targets";
+}
+
+impl SerializeRelation for Targets {}
+
+#[test]
+fn test_relations() {
+    let mut ecs = create_ecs();
+    ecs.register_relation::<Targets>(ChunkSize::Elements(64));
+
+    let player = ecs.spawn();
+    let ally = ecs.spawn();
+    let enemy = ecs.spawn();
+    let boss = ecs.spawn();
+
+    // // Relating // //
+    assert_eq!(ecs.relate(player, enemy, Targets(1)), None);
+    assert_eq!(ecs.relate(player, boss, Targets(2)), None);
+    assert_eq!(ecs.relate(ally, enemy, Targets(3)), None);
+    assert_eq!(ecs.relate(player, enemy, Targets(4)), Some(Targets(1)));
+    ecs.get_relation_mut::<Targets>(ally, enemy).unwrap().0 += 2;
+    assert_eq!(ecs.targets::<Targets>(player).collect::<Vec<_>>(), vec![(enemy, &Targets(4)), (boss, &Targets(2))]);
+    assert_eq!(ecs.sources::<Targets>(enemy).collect::<Vec<_>>(), vec![player, ally]);
+    assert_eq!(ecs.get_relation::<Targets>(ally, enemy), Some(&Targets(5)));
+    assert!(!ecs.is_related::<Targets>(enemy, player));
+    assert_eq!(ecs.relations::<Targets>().len(), 3);
+
+    // // Commands // //
+    let mut commands = Commands::new();
+    let minion = commands.spawn();
+    commands.relate(minion, player, Targets(6));
+    commands.unrelate::<Targets, _, _>(player, boss);
+    let minion = ecs.apply(commands)[0];
+    assert_eq!(ecs.sources::<Targets>(player).collect::<Vec<_>>(), vec![minion]);
+    assert_eq!(ecs.sources::<Targets>(boss).count(), 0);
+    assert_eq!(ecs.unrelate::<Targets>(player, boss), None);
+
+    // // Cleanup on either side // //
+    ecs.despawn(enemy);
+    assert_eq!(ecs.targets::<Targets>(player).count(), 0);
+    assert_eq!(ecs.targets::<Targets>(ally).count(), 0);
+    ecs.despawn(player);
+    assert_eq!(ecs.targets::<Targets>(minion).count(), 0);
+    assert!(ecs.relations::<Targets>().is_empty());
+
+    // // Stale IDs // //
+    ecs.relate(ally, boss, Targets(7));
+    ecs.despawn(boss);
+    let reused = (0..1024).map(|_| ecs.spawn()).find(|v| v.get_idx() == boss.get_idx()).unwrap();
+    assert_eq!(ecs.sources::<Targets>(reused).count(), 0);
+    assert!(!ecs.is_related::<Targets>(ally, reused));
+}
+
+impl SerializeComponent for Position {}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Follows {
+    leader: EntityID,
+    fallback: Option<EntityID>,
+}
+
+impl Component for Follows {
+    const ID: ComponentID = ComponentID(4);
+    const ID_STR: &'static str = "Test_Follows";
+}
+
+impl SerializeComponent for Follows {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.leader.map_entities(map);
+        self.fallback.map_entities(map);
+    }
+}
+
+fn create_serializable_ecs() -> ECS {
+    let mut ecs = create_ecs();
+    ecs.register_component::<Follows>(ChunkSize::Elements(16));
+    ecs.register_serializable::<Position>();
+    ecs.register_serializable::<Follows>();
+    ecs.register_relation::<Targets>(ChunkSize::Elements(16));
+    ecs.register_serializable_relation::<Targets>();
+    ecs
+}
+
+#[test]
+fn test_serialization() {
+    let mut ecs = create_serializable_ecs();
+    let outsider = ecs.spawn();
+    let leader = ecs.spawn();
+    let follower = ecs.spawn();
+    ecs.attach(leader, Position(1, 2));
+    ecs.attach(leader, Velocity(3, 4)); // Not serializable, skipped
+    ecs.attach(follower, Follows{ leader, fallback: Some(outsider) });
+    ecs.set_parent(follower, leader);
+    ecs.relate(follower, leader, Targets(7));
+    ecs.relate(leader, leader, Targets(8));
+    ecs.relate(leader, outsider, Targets(9));
+    ecs.despawn(outsider);
+
+    let check = |ecs: &ECS, map: &EntityMap| {
+        assert_eq!(map.len(), 2);
+        let (leader, follower) = (map.get(leader), map.get(follower));
+        assert_eq!(ecs.get_ref::<Position>(leader), Some(&Position(1, 2)));
+        assert!(!ecs.has::<Velocity>(leader));
+        assert_eq!(ecs.get_ref::<Follows>(follower), Some(&Follows{ leader, fallback: Some(map.get(outsider)) }));
+        assert!(!map.get(outsider).is_valid());
+        assert_eq!(ecs.children(leader), &[follower]);
+        assert_eq!(ecs.get_relation::<Targets>(follower, leader), Some(&Targets(7)));
+        assert_eq!(ecs.get_relation::<Targets>(leader, leader), Some(&Targets(8)));
+        assert_eq!(ecs.relations::<Targets>().len(), 2);
+    };
+
+    // // Binary // //
+    let bytes = ecs.save_binary().unwrap();
+    let mut loaded = create_serializable_ecs();
+    loaded.spawn(); // Offset the loaded entities
+    let map = loaded.load_binary(&bytes).unwrap();
+    check(&loaded, &map);
+
+    // // Text // //
+    let text = ecs.save_text().unwrap();
+    assert!(text.contains("Test_Follows") && text.contains("Test_Targets") && !text.contains("Test_Velocity"));
+    let mut loaded = create_serializable_ecs();
+    let map = loaded.load_text(&text).unwrap();
+    check(&loaded, &map);
+
+    // // Unknown components // //
+    let mut loaded = create_ecs();
+    let error = loaded.load_text(&text).unwrap_err();
+    assert!(error.to_string().contains("\"Test_Position\" isn't registered for serialization"));
+    assert_eq!(loaded.archetypes().iter().map(|v| v.len()).sum::<usize>(), 0);
+}
+
+#[derive(Debug)]
+struct Impostor;
+
+impl Component for Impostor {
+    const ID: ComponentID = ComponentID(1);
+    const ID_STR: &'static str = "Test_Impostor";
+}
+
+#[test]
+fn test_component_ids() {
+    assert_eq!(ComponentID::from_name(""), ComponentID(0x811c9dc5));
+    assert_eq!(ComponentID::from_name("a"), ComponentID(0xe40c292c));
+    assert_eq!(Parent::ID, ComponentID::from_name(Parent::ID_STR));
+    assert_ne!(Parent::ID, Children::ID);
+
+    let mut ecs = ECS::default();
+    ecs.register_component_default::<Position>();
+    let eid = ecs.spawn();
+    ecs.attach(eid, Position(1, 1));
+    assert_eq!(ecs.get_store_ref::<Position>().len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "conflict between \"Test_Impostor\" and \"Test_Position\"")]
+fn test_component_id_conflict() {
+    let mut ecs = create_ecs();
+    ecs.register_component::<Impostor>(ChunkSize::Elements(16));
+}
+
+#[test]
+fn test_dynamic_components() {
+    let mut ecs = create_ecs();
+    let health = DynamicComponentInfo::new("Test_Health", 4, 4);
+    let name = DynamicComponentInfo::of::<String>("Test_Name");
+    assert_eq!(ecs.register_dynamic_component(health), ComponentID::from_name("Test_Health"));
+    ecs.register_dynamic_component(name);
+
+    let a = ecs.spawn();
+    let b = ecs.spawn();
+    assert!(ecs.attach_dynamic(a, DynamicValue::from_bytes(&health, &10u32.to_ne_bytes())));
+    ecs.attach_dynamic(a, unsafe { DynamicValue::from_value(&name, String::from("a")) });
+    ecs.attach_dynamic(b, DynamicValue::from_bytes(&health, &20u32.to_ne_bytes()));
+    ecs.attach(a, Position(0, 0)); // Moves a's dynamic components to another archetype
+
+    let read = |ecs: &ECS, eid| unsafe { *(ecs.get_ptr(eid, health.id()).unwrap() as *const u32) };
+    assert_eq!(read(&ecs, a), 10);
+    assert_eq!(read(&ecs, b), 20);
+    assert_eq!(unsafe { &*(ecs.get_ptr(a, name.id()).unwrap() as *const String) }, "a");
+    assert_eq!(ecs.get_store_any(name.id()).unwrap().layout(), std::alloc::Layout::new::<String>());
+
+    // Writes through the pointer are marked as changed
+    let since = ecs.advance_tick();
+    unsafe { *(ecs.get_mut_ptr(b, health.id()).unwrap() as *mut u32) = 25; }
+    assert_eq!(read(&ecs, b), 25);
+    let at = ecs.archetypes().location(b).unwrap();
+    assert!(ecs.dynamic_store(health.id()).unwrap().get_ticks(at).unwrap().is_changed(since));
+
+    let copy = ecs.clone_dynamic(a, name.id()).unwrap();
+    assert_eq!(format!("{:?}", copy), "\"a\"");
+    assert!(ecs.clone_dynamic(a, health.id()).is_none());
+    assert_eq!(ecs.dynamic_info(health.id()).unwrap().name(), "Test_Health");
+    assert!(ecs.dynamic_info(ComponentID(1)).is_none());
+
+    let mut entities = Vec::new();
+    ecs.entities_with_id(health.id(), &mut entities);
+    entities.sort();
+    assert_eq!(entities, vec![a, b]);
+
+    let mut commands = Commands::new();
+    let c = commands.spawn();
+    commands.attach_dynamic(c, copy);
+    commands.detach_id(a, name.id());
+    let spawned = ecs.apply(commands);
+    assert_eq!(unsafe { &*(ecs.get_ptr(spawned[0], name.id()).unwrap() as *const String) }, "a");
+    assert!(ecs.get_ptr(a, name.id()).is_none());
+
+    ecs.despawn(spawned[0]);
+    assert!(ecs.dynamic_store(name.id()).unwrap().is_empty());
+    assert_eq!(ecs.dynamic_store(health.id()).unwrap().len(), 2);
+}
+
+#[test]
+#[should_panic(expected = "isn't plain data")]
+fn test_dynamic_component_bytes() {
+    DynamicValue::from_bytes(&DynamicComponentInfo::of::<String>("Test_Name"), &[0; 24]);
+}
+
+#[derive(Debug, PartialEq, Reflect)]
+struct Stats {
+    health: u32,
+    target: Option<EntityID>,
+    path: Vec<Position>,
+}
+
+impl Component for Stats {
+    const ID: ComponentID = ComponentID::from_name("Test_Stats");
+    const ID_STR: &'static str = "Test_Stats";
+}
+
+#[test]
+fn test_reflection() {
+    let mut ecs = create_ecs();
+    ecs.register_component::<Stats>(ChunkSize::Elements(16));
+    ecs.register_reflect::<Stats>();
+
+    let registration = ecs.type_registry().get_by_name("Test_Stats").unwrap();
+    assert_eq!(registration.id, Stats::ID);
+    assert_eq!(registration.fields.iter().map(|v| v.name).collect::<Vec<_>>(), vec!["health", "target", "path"]);
+    assert_eq!(ecs.type_registry().get(Stats::ID).unwrap().fields[1].type_name, "Option<EntityID>");
+
+    let eid = ecs.spawn();
+    let other = ecs.spawn();
+    ecs.attach(eid, Stats{ health: 10, target: None, path: vec![Position(1, 2), Position(3, 4)] });
+    assert_eq!(ecs.get_field(eid, Stats::ID, "health").unwrap().downcast_ref::<u32>(), Some(&10));
+    assert_eq!(ecs.get_field(eid, Stats::ID, "path.1.0").unwrap().downcast_ref::<i32>(), Some(&3));
+
+    let since = ecs.advance_tick();
+    ecs.set_field(eid, Stats::ID, "health", Box::new(5u32)).unwrap();
+    ecs.set_field(eid, Stats::ID, "target", box Some(other)).unwrap();
+    ecs.set_field(eid, Stats::ID, "path.0.1", Box::new(7i32)).unwrap();
+    assert_eq!(ecs.get_ref::<Stats>(eid), Some(&Stats{ health: 5, target: Some(other), path: vec![Position(1, 7), Position(3, 4)] }));
+    assert!(ecs.get_ticks::<Stats>(eid).unwrap().is_changed(since));
+
+    // Reached through the store without knowing the type
+    let location = ecs.archetypes().location(eid).unwrap();
+    let stats = ecs.get_store_any(Stats::ID).unwrap().get_reflect(location).unwrap();
+    assert_eq!(stats.type_name(), "Stats");
+    assert_eq!(stats.fields().len(), 3);
+
+    let since = ecs.advance_tick();
+    assert_eq!(ecs.set_field(eid, Stats::ID, "health", Box::new(5i64)), Err(ReflectError::WrongType("u32")));
+    assert_eq!(ecs.set_field(eid, Stats::ID, "mana", Box::new(5u32)), Err(ReflectError::MissingField("mana".into())));
+    assert!(!ecs.get_ticks::<Stats>(eid).unwrap().is_changed(since)); // Failed sets don't mark the component
+    assert_eq!(ecs.get_field(other, Stats::ID, "health").err(), Some(ReflectError::MissingComponent(Stats::ID)));
+    assert_eq!(ecs.get_field(eid, Position::ID, "0").err(), Some(ReflectError::NotReflected(Position::ID)));
+
+    // Types are named as they're written, skipped fields aren't reflected
+    let label = Label{ text: "a", spans: None, cache: Vec::new() };
+    let reflected: &dyn Reflect = &label;
+    assert_eq!(reflected.fields().iter().map(|v| (v.name, v.type_name)).collect::<Vec<_>>(), vec![("text", "&'static str"), ("spans", "Option<Vec<u32>>")]);
+    assert_eq!(reflected.path("text").unwrap().downcast_ref::<&'static str>(), Some(&"a"));
+    assert!(reflected.field("cache").is_none() && label.cache.is_empty());
+}
+
+#[derive(Reflect)]
+struct Label {
+    text: &'static str,
+    spans: Option<Vec<u32>>,
+    #[reflect(skip)]
+    cache: Vec<(u32, u32)>,
+}
+
+macro_rules! numbered_components {
+    ($($name:ident),*) => {$(
+        #[derive(Debug, PartialEq)]
+        struct $name(u32);
+
+        impl Component for $name {
+            const ID: ComponentID = ComponentID::from_name(concat!("Test_", stringify!($name)));
+            const ID_STR: &'static str = concat!("Test_", stringify!($name));
+        }
+    )*};
+}
+
+numbered_components!(N0, N1, N2, N3, N4, N5, N6, N7, N8, N9, N10, N11);
+
+#[test]
+fn test_wide_queries() {
+    let mut ecs = ECS::default();
+    ecs.register_component::<N0>(ChunkSize::Elements(16));
+    ecs.register_component::<N1>(ChunkSize::Elements(16));
+    ecs.register_component::<N2>(ChunkSize::Elements(16));
+    ecs.register_component::<N3>(ChunkSize::Elements(16));
+    ecs.register_component::<N4>(ChunkSize::Elements(16));
+    ecs.register_component::<N5>(ChunkSize::Elements(16));
+    ecs.register_component::<N6>(ChunkSize::Elements(16));
+    ecs.register_component::<N7>(ChunkSize::Elements(16));
+    ecs.register_component::<N8>(ChunkSize::Elements(16));
+    ecs.register_component::<N9>(ChunkSize::Elements(16));
+    ecs.register_component::<N10>(ChunkSize::Elements(16));
+    ecs.register_component::<N11>(ChunkSize::Elements(16));
+
+    let full = ecs.spawn();
+    let partial = ecs.spawn();
+    for eid in [full, partial].iter().copied() {
+        ecs.attach(eid, N0(0)); ecs.attach(eid, N1(1)); ecs.attach(eid, N2(2));  ecs.attach(eid, N3(3));
+        ecs.attach(eid, N4(4)); ecs.attach(eid, N5(5)); ecs.attach(eid, N6(6));  ecs.attach(eid, N7(7));
+        ecs.attach(eid, N8(8)); ecs.attach(eid, N9(9)); ecs.attach(eid, N10(10));
+    }
+    ecs.attach(full, N11(11));
+
+    type Flat   = ((N0, N1, N2, N3, N4, N5, N6, N7, N8, N9, N10, N11), ());
+    type Nested = (((N0, N1, N2), (N3, (N4, N5)), N6, N7, N8, N9, N10, N11), ());
+    assert_eq!(<Flat as ComponentRequestTupleDefinition>::query_id(), <Nested as ComponentRequestTupleDefinition>::query_id());
+    assert_eq!(<Flat as ComponentRequestTupleDefinition>::query_id().len(), 12);
+
+    ecs.register_query::<Flat>();
+    assert_eq!(ecs.query::<Flat>().map(|v| v.0).collect::<Vec<_>>(), vec![full]);
+    let (eid, ((_, _, n2), (n3, (_, n5)), ..), ()) = ecs.query::<Nested>().next().unwrap();
+    assert_eq!((eid, n2, n3, n5), (full, &N2(2), &N3(3), &N5(5)));
+
+    // Mutable and optional elements nest too
+    type NestedMut = (((Mut<N0>, N1), (N2, N3, N4, N5, N6, N7, N8, (N9, Mut<N10>))), ((N11,),));
+    ecs.register_query_mut::<NestedMut>();
+    for (_, ((n0, _), (.., (_, n10))), ((n11,),)) in ecs.query_mut::<NestedMut>() {
+        n0.0 += n11.map_or(100, |v| v.0);
+        n10.0 += 1;
+    }
+    assert_eq!(ecs.get_ref::<N0>(full), Some(&N0(11)));
+    assert_eq!(ecs.get_ref::<N0>(partial), Some(&N0(100)));
+    assert_eq!(ecs.get_ref::<N10>(partial), Some(&N10(11)));
+    assert!(accesses_of::<NestedMut>().iter().any(|v| v.id == N10::ID && v.mutable));
+
+    // More or-groups than fit inline
+    type Groups = ((N0,), (), (Or<(N1, N11)>, Or<(N2,)>, Or<(N3,)>, Or<(N4,)>, Or<(N5,)>, Or<(N6,)>, Or<(N7, N11)>));
+    ecs.register_query::<Groups>();
+    assert_eq!(ecs.query::<Groups>().count(), 2);
+    ecs.detach::<N7>(partial);
+    assert_eq!(ecs.query::<Groups>().map(|v| v.0).collect::<Vec<_>>(), vec![full]);
+}
+
+#[test]
+fn test_query_order() {
+    let run = || {
+        let mut ecs = create_ecs();
+        ecs.register_query::<((Position,), ())>();
+        let entities = (0..6).map(|_| ecs.spawn()).collect::<Vec<_>>();
+        for (i, eid) in entities.iter().rev().enumerate() {
+            ecs.attach(*eid, Position(i as i32, 0));
+        }
+        assert_eq!(ecs.query::<((Position,), ())>().map(|v| v.0).collect::<Vec<_>>(), entities.iter().rev().copied().collect::<Vec<_>>());
+
+        // The last match moves into the gap
+        ecs.detach::<Position>(entities[4]);
+        ecs.despawn(entities[2]);
+        ecs.attach(entities[4], Position(9, 9));
+        let result = ecs.query::<((Position,), ())>().map(|v| v.0).collect::<Vec<_>>();
+        assert_eq!(result, vec![entities[5], entities[0], entities[3], entities[1], entities[4]]);
+        result
+    };
+    assert_eq!(run(), run());
+}
+
+fn create_world() -> ECS {
+    let mut ecs = create_serializable_ecs();
+    ecs.register_component::<Stats>(ChunkSize::Elements(16));
+    ecs.register_reflect::<Stats>();
+    ecs.register_query::<((Position,), ())>();
+    ecs
+}
+
+#[test]
+fn test_worlds() {
+    let mut level = create_world();
+    let root = level.spawn();
+    let child = level.spawn();
+    let guard = level.spawn();
+    level.attach(root, Position(1, 1));
+    level.attach(child, Follows{ leader: root, fallback: Some(root) });
+    level.set_parent(child, root);
+    level.attach(guard, Stats{ health: 3, target: Some(child), path: Vec::new() });
+    level.relate(guard, child, Targets(4));
+
+    let mut live = create_world();
+    let player = live.spawn();
+    live.attach(player, Position(0, 0));
+
+    let map = live.merge(level);
+    assert_eq!(map.len(), 3);
+    let (root, child, guard) = (map.get(root), map.get(child), map.get(guard));
+    assert_eq!(live.query::<((Position,), ())>().count(), 2);
+    assert_eq!(live.get_ref::<Follows>(child), Some(&Follows{ leader: root, fallback: Some(root) }));
+    assert_eq!(live.children(root), &[child]);
+    assert_eq!(live.get_ref::<Stats>(guard).unwrap().target, Some(child)); // Remapped through reflection
+    assert_eq!(live.get_relation::<Targets>(guard, child), Some(&Targets(4)));
+    assert!(live.archetypes().iter().skip(1).all(|v| !v.entities().is_empty())); // No archetype per attached component
+
+    // Moving a child alone cuts it from its parent, moving a parent takes its subtree along
+    live.relate(guard, root, Targets(1));
+    let mut preview = create_world();
+    let moved = live.move_entities_into(&mut preview, &[child]);
+    assert!(!live.is_alive(child));
+    assert!(live.children(root).is_empty());
+    assert_eq!(preview.parent(moved.get(child)), None);
+    assert_eq!(preview.get_ref::<Follows>(moved.get(child)).unwrap().leader, EntityID::new()); // Left behind
+
+    let child = live.spawn();
+    live.set_parent(child, root);
+    live.relate(child, root, Targets(2));
+    let moved = live.move_entities_into(&mut preview, &[root]);
+    assert_eq!(moved.len(), 2);
+    assert!(!live.is_alive(root) && !live.is_alive(child));
+    assert_eq!(preview.children(moved.get(root)), &[moved.get(child)]);
+    assert_eq!(preview.get_relation::<Targets>(moved.get(child), moved.get(root)), Some(&Targets(2))); // Both ends moved
+    assert_eq!(live.targets::<Targets>(guard).count(), 0);
+    assert_eq!(preview.sources::<Targets>(moved.get(root)).count(), 1);
+    assert_eq!(live.query::<((Position,), ())>().map(|v| v.0).collect::<Vec<_>>(), vec![player]);
+    assert_eq!(preview.query::<((Position,), ())>().map(|v| v.0).collect::<Vec<_>>(), vec![moved.get(root)]);
+}
+
+#[test]
+#[should_panic(expected = "ComponentStore not registered in the destination for \"Test_Stats\"")]
+fn test_worlds_missing_store() {
+    let mut ecs = create_world();
+    let eid = ecs.spawn();
+    ecs.attach(eid, Stats{ health: 3, target: None, path: Vec::new() });
+    ecs.move_entities_into(&mut create_ecs(), &[eid]);
+}
+
+#[derive(Clone, Bundle)]
+struct Body {
+    position: Position,
+    velocity: Velocity,
+}
+
+#[test]
+fn test_bundles() {
+    let mut ecs = create_ecs();
+    ecs.register_query::<((Position, Velocity), ())>();
+    ecs.register_query::<((Position,), (), (Without<Velocity>,))>();
+
+    // Nested bundles are flattened, and wider than a single request
+    ecs.register_component::<N0>(ChunkSize::Elements(16));
+    ecs.register_component::<N1>(ChunkSize::Elements(16));
+    ecs.register_component::<N2>(ChunkSize::Elements(16));
+    ecs.register_component::<N3>(ChunkSize::Elements(16));
+    ecs.register_component::<N4>(ChunkSize::Elements(16));
+    ecs.register_component::<N5>(ChunkSize::Elements(16));
+    ecs.register_component::<N6>(ChunkSize::Elements(16));
+    ecs.register_component::<N7>(ChunkSize::Elements(16));
+    ecs.register_component::<N8>(ChunkSize::Elements(16));
+    ecs.register_component::<N9>(ChunkSize::Elements(16));
+    ecs.register_component::<N10>(ChunkSize::Elements(16));
+    ecs.register_component::<N11>(ChunkSize::Elements(16));
+    let wide = ecs.spawn_bundle(((N0(0), N1(1), N2(2), N3(3), N4(4), N5(5)), (N6(6), N7(7), N8(8), N9(9), N10(10)), N11(11)));
+    assert_eq!(ecs.get_ref::<N0>(wide), Some(&N0(0)));
+    assert_eq!(ecs.get_ref::<N11>(wide), Some(&N11(11)));
+
+    let a = ecs.spawn_bundle(Body{ position: Position(1, 1), velocity: Velocity(2, 2) });
+    let b = ecs.spawn_bundle((Position(3, 3),));
+    assert_eq!(ecs.query::<((Position, Velocity), ())>().map(|v| v.0).collect::<Vec<_>>(), vec![a]);
+    assert_eq!(ecs.query::<((Position,), (), (Without<Velocity>,))>().map(|v| v.0).collect::<Vec<_>>(), vec![b]);
+
+    // Replaces the components the entity has, adds the rest
+    ecs.insert_bundle(b, (Position(4, 4), Velocity(5, 5), Frozen));
+    assert_eq!(ecs.get_ref::<Position>(b), Some(&Position(4, 4)));
+    assert_eq!(ecs.get_ref::<Velocity>(b), Some(&Velocity(5, 5)));
+    assert!(ecs.get_ref::<Frozen>(b).is_some());
+    assert_eq!(ecs.query::<((Position, Velocity), ())>().count(), 2);
+    assert_eq!(ecs.query::<((Position,), (), (Without<Velocity>,))>().count(), 0);
+    assert_eq!(ecs.get_ref::<Position>(a), Some(&Position(1, 1))); // Rows stay in step with the archetype
+
+    // Every instance gets its own copy, overrides only touch the root
+    let prefab = Prefab::new(Body{ position: Position(0, 0), velocity: Velocity(1, 0) })
+        .with(Velocity(0, 1))
+        .with_child(Prefab::new((Position(0, 0),)));
+    let first = ecs.instantiate(&prefab);
+    let second = ecs.instantiate_with(&prefab, (Position(9, 9),));
+    assert_eq!(ecs.get_ref::<Velocity>(first), Some(&Velocity(0, 1)));
+    assert_eq!(ecs.get_ref::<Position>(second), Some(&Position(9, 9)));
+    ecs.get_mut::<Position>(first).unwrap().0 = 7;
+    assert_eq!(ecs.get_ref::<Position>(first), Some(&Position(7, 0)));
+
+    let (child_a, child_b) = (ecs.children(first)[0], ecs.children(second)[0]);
+    assert_ne!(child_a, child_b);
+    assert_eq!(ecs.get_ref::<Position>(child_b), Some(&Position(0, 0)));
+    assert_eq!(ecs.parent(child_b), Some(second));
+}
+
+#[test]
+#[should_panic(expected = "Component \"Test_Position\" appears more than once in a bundle")]
+fn test_bundles_duplicate() {
+    let mut ecs = create_ecs();
+    ecs.spawn_bundle((Position(0, 0), (Velocity(0, 0), Position(1, 1))));
+}
+
+#[test]
+fn test_derive() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("ui/component_pass.rs");
+    cases.compile_fail("ui/component_fail.rs");
+}
@@ -0,0 +1,52 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+use std::collections::HashMap;
+
+use butterscotch_ecs::EntityID;
+use butterscotch_ecs_derive::Component;
+
+#[derive(Debug, Component)]
+struct MissingNamespace;
+
+#[derive(Debug, Component)]
+#[component(namespace = "")]
+struct EmptyNamespace;
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test Space")]
+struct SpacedNamespace;
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test", chunk_size = 0)]
+struct EmptyChunk;
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test", layout = "table")]
+struct UnknownOption;
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test", storage = "sparse")]
+struct UnknownStorage;
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test")]
+struct Borrowed<'a>(&'a u32);
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test")]
+struct Fixed<const N: usize>([u32; N]);
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test", serialize)]
+struct Unsupported {
+    targets: HashMap<EntityID, u32>,
+}
+
+#[derive(Component)]
+#[component(namespace = "Test")]
+union Union {
+    a: u32,
+}
+
+fn main() {}
@@ -0,0 +1,103 @@
+/* ************************************************************************ **
+** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
+** ************************************************************************ */
+use butterscotch_common::container::{ChunkSize, GID};
+use butterscotch_ecs::{Component, ComponentID, ComponentName, ComponentStorage, EntityID, EntityMap, MapEntities, SerializeComponent};
+use butterscotch_ecs_derive::Component;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Component)]
+#[component(namespace = "Test", chunk_size = 16)]
+struct Chunked(u32);
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test", storage = "table")]
+struct Tabled;
+
+#[derive(Debug, Clone, Component)]
+#[component_ns = "Legacy"]
+struct Legacy;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Component)]
+#[component(namespace = "Test", serialize)]
+struct Link {
+    target: EntityID,
+    parent: Option<EntityID>,
+    children: Vec<EntityID>,
+    weight: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Component)]
+#[component(namespace = "Test", serialize)]
+enum Shape {
+    Point,
+    Joint(EntityID, u32),
+    Chain{ links: Vec<EntityID> },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Component)]
+#[component(namespace = "Test", serialize)]
+struct Nested {
+    pair: (EntityID, u32),
+    ring: [EntityID; 2],
+    boxed: Box<EntityID>,
+    groups: Option<Vec<(EntityID, EntityID)>>,
+}
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test")]
+struct Wrapper<T>(T);
+
+#[derive(Debug, Component)]
+#[component(namespace = "Test", chunk_size = 8)]
+struct Pair<A, B: Clone> where A: Clone {
+    a: A,
+    b: B,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Component)]
+#[component(namespace = "Test", serialize)]
+struct Holder<T> {
+    inner: T,
+    owner: EntityID,
+}
+
+fn main() {
+    assert_eq!(Chunked::ID, ComponentID::from_name("Test_Chunked"));
+    assert_eq!(Chunked::ID_STR, "Test_Chunked");
+    assert!(matches!(Chunked::CHUNK_SIZE, ChunkSize::Elements(16)));
+    assert!(matches!(Legacy::CHUNK_SIZE, ChunkSize::Elements(1024)));
+    assert_eq!(Legacy::ID_STR, "Legacy_Legacy");
+    assert_eq!(Tabled::STORAGE, ComponentStorage::Table);
+
+    assert_eq!(Wrapper::<Chunked>::ID_STR, "Test_Wrapper<Test_Chunked>");
+    assert_eq!(Wrapper::<Chunked>::ID, ComponentID::from_name("Test_Wrapper<Test_Chunked>"));
+    assert_ne!(Wrapper::<Chunked>::ID, Wrapper::<Legacy>::ID);
+    assert_eq!(Wrapper::<Wrapper<Legacy>>::ID_STR, "Test_Wrapper<Test_Wrapper<Legacy_Legacy>>");
+    assert_eq!(Pair::<Chunked, Legacy>::ID_STR, "Test_Pair<Test_Chunked, Legacy_Legacy>");
+    assert!(matches!(Pair::<Chunked, Legacy>::CHUNK_SIZE, ChunkSize::Elements(8)));
+
+    let saved = GID::new().with_idx(1);
+    let loaded = GID::new().with_idx(2);
+    let mut map = EntityMap::default();
+    map.insert(saved, loaded);
+
+    let mut link = Link{ target: saved, parent: Some(saved), children: vec![saved], weight: 1 };
+    SerializeComponent::map_entities(&mut link, &map);
+    assert_eq!(link, Link{ target: loaded, parent: Some(loaded), children: vec![loaded], weight: 1 });
+
+    let mut shapes = vec![Shape::Point, Shape::Joint(saved, 1), Shape::Chain{ links: vec![saved, saved] }];
+    for shape in &mut shapes {
+        SerializeComponent::map_entities(shape, &map);
+    }
+    assert_eq!(shapes, vec![Shape::Point, Shape::Joint(loaded, 1), Shape::Chain{ links: vec![loaded, loaded] }]);
+
+    let mut nested = Nested{ pair: (saved, 1), ring: [saved; 2], boxed: Box::new(saved), groups: Some(vec![(saved, saved)]) };
+    SerializeComponent::map_entities(&mut nested, &map);
+    assert_eq!(nested, Nested{ pair: (loaded, 1), ring: [loaded; 2], boxed: Box::new(loaded), groups: Some(vec![(loaded, loaded)]) });
+
+    let mut holder = Holder{ inner: Link{ target: saved, parent: None, children: Vec::new(), weight: 1 }, owner: saved };
+    SerializeComponent::map_entities(&mut holder, &map);
+    assert_eq!(holder, Holder{ inner: Link{ target: loaded, parent: None, children: Vec::new(), weight: 1 }, owner: loaded });
+    assert_eq!(Holder::<Link>::ID_STR, "Test_Holder<Test_Link>");
+}
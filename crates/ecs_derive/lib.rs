@@ -1,106 +1,425 @@
 /* ************************************************************************ **
 ** * ©2020 Michael Baker (butterscotch@notvery.moe) | Apache License v2.0 * **
 ** ************************************************************************ */
-#![feature(with_options)]
-
 extern crate proc_macro;
 
-use std::{collections::HashMap, io::{Read, Write}, path::Path};
-
-use fs2::FileExt;
-use regex::Regex;
-use syn::{DeriveInput, Lit, Meta, parse_macro_input};
 use proc_macro::TokenStream;
+use proc_macro2::{Delimiter, Span, TokenStream as TokenStream2, TokenTree};
+use quote::{ToTokens, format_ident, quote};
+use syn::{Attribute, Data, DeriveInput, Error, Field, GenericArgument, GenericParam, Ident, Index, Lit, LitStr, Member, Meta, NestedMeta, PathArguments, Result, Type, parse_macro_input, parse_quote};
 
-
-#[proc_macro_derive(Component, attributes(component_ns))]
+/// Options are read from `#[component(namespace = "...", chunk_size = 1024, storage = "table", serialize)]`,
+/// the namespace can also be given by `#[component_ns = "..."]`. Serialization also implements SerializeComponent,
+/// remapping EntityIDs held directly or through Option, Vec, Box, arrays, tuples and type parameters. Type parameters
+/// must be components, the ID of each instantiation is named after their ID_STRs, ie. `NS_Wrapper<NS_Position>`.
+#[proc_macro_derive(Component, attributes(component, component_ns))]
 pub fn derive_component(input: TokenStream) -> TokenStream {
-    /* This is all awful. Never do this. Please. But anyway... */
-    /* We make the macro stateful using IO relative to where the build command is run */
-    let (ident, namespace) = parse_input(parse_macro_input!(input));
-    let name = &format!("{}_{}", namespace, ident);
-    let id = get_or_generate_id(name);
-    format!(
-        r#"impl Component for {} {{
-            const ID: ComponentID = ComponentID({});
-            const ID_STR: &'static str = "{}";
-        }}"#, ident, id, name
-    ).parse().unwrap()
-}
-
-fn parse_input<'a>(input: DeriveInput) -> (String, String) {
-    let DeriveInput { attrs, ident, data, .. } = input;
-
-    if !(matches!(data, syn::Data::Struct(_))) {
-        panic!("Usage of #[Component] on a non-struct type");
-    }
-
-    let namespace = match attrs.get(0) {
-        Some(v) => {
-            if let Meta::NameValue(v) = v.parse_meta().unwrap() {
-                if let Lit::Str(v) = v.lit {
-                    let v = v.value();
-                    if Regex::new(r"\s").unwrap().is_match(&v) { panic!("Namespace cannot contain whitespace") }
-                    v
-                } else {
-                    panic!("Expected string literal");
+    let input = parse_macro_input!(input as DeriveInput);
+    match derive(input) {
+        Ok(v)  => v.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Fields are named as they're declared, tuple struct fields by their index. Every field's type must implement
+/// Reflect, unless the field is skipped with `#[reflect(skip)]`.
+#[proc_macro_derive(Reflect, attributes(reflect))]
+pub fn derive_reflect(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match reflect(input) {
+        Ok(v)  => v.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Every field must be a Bundle, ie. a component or a tuple of them, and is attached in declaration order
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match bundle(input) {
+        Ok(v)  => v.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct Options {
+    namespace: Option<LitStr>,
+    chunk_size: Option<usize>,
+    storage: Option<Ident>,
+    serialize: bool,
+}
+
+fn derive(mut input: DeriveInput) -> Result<TokenStream2> {
+    if let Data::Union(v) = &input.data {
+        return Err(Error::new(v.union_token.span, "Component can't be derived for unions"));
+    }
+
+    let options = parse_options(&input.attrs)?;
+    let namespace = match &options.namespace {
+        Some(v) => v.value(),
+        None    => return Err(Error::new(input.ident.span(), "Missing namespace, add #[component(namespace = \"...\")]")),
+    };
+    let ident = &input.ident;
+    let name = format!("{}_{}", namespace, ident);
+
+    // Every instantiation needs its own ID, so it's named after its type arguments
+    let mut params = Vec::new();
+    for param in &input.generics.params {
+        match param {
+            GenericParam::Type(v)     => params.push(v.ident.clone()),
+            GenericParam::Lifetime(v) => return Err(Error::new_spanned(v, "Components must be 'static, lifetime parameters aren't supported")),
+            GenericParam::Const(v)    => return Err(Error::new_spanned(v, "Const parameters aren't supported, the ComponentID is built from the type parameters")),
+        }
+    }
+    for param in input.generics.type_params_mut() {
+        match options.serialize {
+            true  => param.bounds.push(parse_quote!(SerializeComponent)),
+            false => param.bounds.push(parse_quote!(Component)),
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let chunk_size = options.chunk_size.map(|v| quote!{ const CHUNK_SIZE: ChunkSize = ChunkSize::Elements(#v); });
+    let storage = options.storage.map(|v| quote!{ const STORAGE: ComponentStorage = ComponentStorage::#v; });
+    let mut result = match params.is_empty() {
+        true => quote!{
+            impl #impl_generics Component for #ident #ty_generics #where_clause {
+                const ID: ComponentID = ComponentID::from_name(#name);
+                const ID_STR: &'static str = #name;
+                #chunk_size
+                #storage
+            }
+        },
+        false => {
+            let open = format!("{}<", name);
+            let parts = params.iter().enumerate().map(|(i, v)| match i {
+                0 => quote!{ <#v as Component>::ID_STR },
+                _ => quote!{ ", ", <#v as Component>::ID_STR },
+            });
+            quote!{
+                impl #impl_generics #ident #ty_generics #where_clause {
+                    #[doc(hidden)]
+                    const __COMPONENT_NAME: &'static ComponentName = &ComponentName::new(&[#open, #(#parts,)* ">"]);
+                }
+
+                impl #impl_generics Component for #ident #ty_generics #where_clause {
+                    const ID: ComponentID = ComponentID::from_name(Self::ID_STR);
+                    const ID_STR: &'static str = Self::__COMPONENT_NAME.as_str();
+                    #chunk_size
+                    #storage
                 }
-            } else {
-                panic!("Expected namespace = '<value>'");
             }
         },
-        _ => panic!("Namespace string required"),
     };
 
-    return (ident.to_string(), namespace);
+    if options.serialize {
+        let map_entities = map_entities(&input.data, &params)?;
+        result.extend(quote!{
+            impl #impl_generics SerializeComponent for #ident #ty_generics #where_clause {
+                fn map_entities(&mut self, map: &EntityMap) {
+                    #map_entities
+                }
+            }
+        });
+    }
+    return Ok(result);
 }
 
-fn get_or_generate_id(name: &str) -> u64 {
-    let base_dir = Path::new("./build/generated/butterscotch_ecs/");
-    std::fs::create_dir_all(base_dir.clone()).expect("Could not create output directory");
-    
-    let filename = base_dir.join("gen_component_ids.txt");
-    let mut file = std::fs::File::with_options().create(true).read(true).write(true).open(filename).expect("Could not open file.");
-    file.lock_exclusive().expect("could not lock file");
+fn reflect(mut input: DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(v) => &v.fields,
+        Data::Enum(v)   => return Err(Error::new(v.enum_token.span, "Reflect can only be derived for structs")),
+        Data::Union(v)  => return Err(Error::new(v.union_token.span, "Reflect can only be derived for structs")),
+    };
+
+    let mut reflected = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        if is_skipped(&field.attrs)? { continue; }
+        let member = member(i, field);
+        let name = match &field.ident {
+            Some(ident) => ident.to_string(),
+            None        => i.to_string(),
+        };
+        let type_name = type_string(field.ty.to_token_stream());
+        reflected.push((member, name, type_name));
+    }
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(Reflect));
+    }
+    let ident = &input.ident;
+    let ident_str = ident.to_string();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let infos = reflected.iter().map(|(_, name, type_name)| quote!{ FieldInfo::new(#name, #type_name) });
+    let refs = reflected.iter().map(|(member, name, _)| quote!{ #name => Some(&self.#member) });
+    let muts = reflected.iter().map(|(member, name, _)| quote!{ #name => Some(&mut self.#member) });
+
+    return Ok(quote!{
+        impl #impl_generics Reflect for #ident #ty_generics #where_clause {
+            fn field_infos() -> &'static [FieldInfo] {
+                const FIELDS: &[FieldInfo] = &[#(#infos),*];
+                FIELDS
+            }
 
-    let mut contents = "".to_owned();
-    file.read_to_string(&mut contents).expect("Failed to read file");
-    let id_map = construct_id_map(&contents);
+            fn type_name(&self) -> &'static str { #ident_str }
+            fn fields(&self) -> &'static [FieldInfo] { Self::field_infos() }
 
-    let id = match id_map.get(name) {
-        Some(v) => *v,
-        None => {
-            let mut ids = id_map.values().collect::<Vec<_>>();
-            ids.sort();
+            fn field(&self, name: &str) -> Option<&dyn Reflect> {
+                match name { #(#refs,)* _ => None }
+            }
 
-            let mut prev_id = 0;
-            for id in ids {
-                let diff = id - prev_id;
-                if diff > 1 { break; }
-                prev_id = *id;
+            fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+                match name { #(#muts,)* _ => None }
             }
 
-            let next_id = prev_id + 1;
-            file.write_fmt(format_args!("{} {}\n", name, next_id)).expect("Could not write id to file");
-            next_id
+            fn as_any(&self)         -> &dyn std::any::Any     { self }
+            fn as_any_mut(&mut self) -> &mut dyn std::any::Any { self }
+
+            fn set(&mut self, value: Box<dyn std::any::Any>) -> Result<(), Box<dyn std::any::Any>> {
+                *self = *value.downcast::<Self>()?;
+                return Ok(());
+            }
+        }
+    });
+}
+
+/// A type as it's usually written, ie. `&'static str` and `Vec<(u32, u32)>`
+fn type_string(tokens: TokenStream2) -> String {
+    let mut result = String::new();
+    write_type(&mut result, tokens);
+    return result;
+}
+
+fn write_type(out: &mut String, tokens: TokenStream2) {
+    let mut word = false; // Consecutive identifiers and literals are separated by a space
+    for token in tokens {
+        match token {
+            TokenTree::Ident(_) | TokenTree::Literal(_) => {
+                if word { out.push(' '); }
+                out.push_str(&token.to_string());
+                word = true;
+            },
+            TokenTree::Punct(v) => {
+                out.push(v.as_char());
+                if matches!(v.as_char(), ',' | ';') { out.push(' '); }
+                word = false;
+            },
+            TokenTree::Group(v) => {
+                let (open, close) = match v.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket     => ("[", "]"),
+                    Delimiter::Brace       => ("{", "}"),
+                    Delimiter::None        => ("", ""),
+                };
+                out.push_str(open);
+                write_type(out, v.stream());
+                while out.ends_with(' ') { out.pop(); }
+                out.push_str(close);
+                word = false;
+            },
         }
+    }
+}
+
+fn bundle(mut input: DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(v) => &v.fields,
+        Data::Enum(v)   => return Err(Error::new(v.enum_token.span, "Bundle can only be derived for structs")),
+        Data::Union(v)  => return Err(Error::new(v.union_token.span, "Bundle can only be derived for structs")),
     };
 
-    file.unlock().expect("Failed to unlock file. Clean cargo and try again.");
+    let types = fields.iter().map(|v| v.ty.clone()).collect::<Vec<_>>();
+    let members = fields.iter().enumerate().map(|(i, field)| member(i, field)).collect::<Vec<_>>();
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(Bundle));
+    }
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    return Ok(quote!{
+        impl #impl_generics Bundle for #ident #ty_generics #where_clause {
+            fn ids(out: &mut ComponentIDs) {
+                #(<#types as Bundle>::ids(out);)*
+            }
+
+            fn write(self, writer: &mut BundleWriter) {
+                #(Bundle::write(self.#members, writer);)*
+            }
+        }
+    });
+}
+
+fn is_skipped(attrs: &[Attribute]) -> Result<bool> {
+    let mut result = false;
+    for attr in attrs.iter().filter(|v| v.path.is_ident("reflect")) {
+        match attr.parse_meta()? {
+            Meta::List(v) => for nested in &v.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::Path(v)) if v.is_ident("skip") => result = true,
+                    v => return Err(Error::new_spanned(v, "Unknown option, expected skip")),
+                }
+            },
+            v => return Err(Error::new_spanned(v, "Expected #[reflect(skip)]")),
+        }
+    }
+    return Ok(result);
+}
+
+fn parse_options(attrs: &[Attribute]) -> Result<Options> {
+    let mut options = Options{ namespace: None, chunk_size: None, storage: None, serialize: false };
+
+    for attr in attrs {
+        if attr.path.is_ident("component_ns") {
+            match attr.parse_meta()? {
+                Meta::NameValue(v) => options.namespace = Some(parse_namespace(&v.lit)?),
+                v                  => return Err(Error::new_spanned(v, "Expected #[component_ns = \"...\"]")),
+            }
+            continue;
+        }
+        if !attr.path.is_ident("component") { continue; }
+
+        let list = match attr.parse_meta()? {
+            Meta::List(v) => v,
+            v             => return Err(Error::new_spanned(v, "Expected #[component(...)]")),
+        };
+        for nested in list.nested {
+            let meta = match nested {
+                NestedMeta::Meta(v) => v,
+                v                   => return Err(Error::new_spanned(v, "Expected an option, ie. namespace = \"...\"")),
+            };
+            match &meta {
+                Meta::Path(v) if v.is_ident("serialize") => options.serialize = true,
+                Meta::NameValue(v) if v.path.is_ident("namespace") => options.namespace = Some(parse_namespace(&v.lit)?),
+                Meta::NameValue(v) if v.path.is_ident("chunk_size") => options.chunk_size = Some(match &v.lit {
+                    Lit::Int(v) => match v.base10_parse::<usize>()? {
+                        0 => return Err(Error::new_spanned(v, "Chunk size must be at least one element")),
+                        v => v,
+                    },
+                    v => return Err(Error::new_spanned(v, "Expected the number of elements per chunk")),
+                }),
+                Meta::NameValue(v) if v.path.is_ident("storage") => options.storage = Some(match &v.lit {
+                    Lit::Str(v) if v.value() == "table" => Ident::new("Table", v.span()),
+                    v => return Err(Error::new_spanned(v, "Expected storage = \"table\", the only storage for now")),
+                }),
+                v => return Err(Error::new_spanned(v, "Unknown option, expected namespace, chunk_size, storage or serialize")),
+            }
+        }
+    }
+    return Ok(options);
+}
+
+fn parse_namespace(lit: &Lit) -> Result<LitStr> {
+    match lit {
+        Lit::Str(v) if v.value().is_empty() => Err(Error::new_spanned(v, "Namespace cannot be empty")),
+        Lit::Str(v) if v.value().chars().any(char::is_whitespace) => Err(Error::new_spanned(v, "Namespace cannot contain whitespace")),
+        Lit::Str(v) => Ok(v.clone()),
+        v           => Err(Error::new_spanned(v, "Expected string literal")),
+    }
+}
 
-    return id;
+/// Remaps the EntityIDs held by every field, see map_type
+fn map_entities(data: &Data, params: &[Ident]) -> Result<TokenStream2> {
+    match data {
+        Data::Struct(v) => {
+            let mut calls = Vec::new();
+            for (i, field) in v.fields.iter().enumerate() {
+                let member = member(i, field);
+                calls.extend(map_type(&field.ty, quote!{ &mut self.#member }, params, 0)?);
+            }
+            Ok(quote!{ #(#calls)* })
+        },
+        Data::Enum(v) => {
+            let mut variants = Vec::new();
+            for variant in &v.variants {
+                let ident = &variant.ident;
+                let mut bindings = Vec::new();
+                let mut calls = Vec::new();
+                for (i, field) in variant.fields.iter().enumerate() {
+                    let binding = format_ident!("__{}", i);
+                    let call = match map_type(&field.ty, quote!{ #binding }, params, 0)? {
+                        Some(v) => v,
+                        None    => continue,
+                    };
+                    let member = member(i, field);
+                    bindings.push(quote!{ #member: #binding });
+                    calls.push(call);
+                }
+                variants.push(quote!{ Self::#ident{ #(#bindings,)* .. } => { #(#calls)* } });
+            }
+            Ok(quote!{
+                #[allow(unreachable_patterns)]
+                match self { #(#variants)* _ => {} }
+            })
+        },
+        Data::Union(_) => Ok(quote!{}),
+    }
 }
 
+fn member(index: usize, field: &Field) -> Member {
+    match &field.ident {
+        Some(ident) => Member::Named(ident.clone()),
+        None        => Member::Unnamed(Index{ index: index as u32, span: Span::call_site() }),
+    }
+}
 
-fn construct_id_map(input: &str) -> HashMap<String, u64> {
-    let mut map = HashMap::<String, u64>::new();
-    let regex = Regex::new(r#"([a-zA-Z0-9_]+)\s+(\d+)"#).expect("Could not construct regex");
+/// Remaps the EntityIDs held by a value, given as an expression borrowing it mutably. EntityIDs are found directly,
+/// or through Option, Vec, Box, arrays, tuples and type parameters. None if the type doesn't hold any, an error if it
+/// names EntityID in any other way, ie. `HashMap<EntityID, T>`.
+fn map_type(ty: &Type, value: TokenStream2, params: &[Ident], depth: usize) -> Result<Option<TokenStream2>> {
+    let binding = format_ident!("__v{}", depth);
+    let result = match ty {
+        Type::Paren(v) => return map_type(&v.elem, value, params, depth),
+        Type::Group(v) => return map_type(&v.elem, value, params, depth),
+        Type::Array(v) => map_type(&v.elem, quote!{ #binding }, params, depth + 1)?.map(|inner| quote!{
+            for #binding in (#value).iter_mut() { #inner }
+        }),
+        Type::Tuple(v) => {
+            let mut calls = Vec::new();
+            for (i, elem) in v.elems.iter().enumerate() {
+                let index = Index{ index: i as u32, span: Span::call_site() };
+                calls.extend(map_type(elem, quote!{ &mut (#value).#index }, params, depth + 1)?);
+            }
+            match calls.is_empty() {
+                true  => None,
+                false => Some(quote!{ #(#calls)* }),
+            }
+        },
+        Type::Path(v) if v.qself.is_none() => {
+            let segment = v.path.segments.last().unwrap();
+            let argument = match &segment.arguments {
+                PathArguments::AngleBracketed(v) if v.args.len() == 1 => match v.args.first() {
+                    Some(GenericArgument::Type(v)) => Some(v),
+                    _                              => None,
+                },
+                _ => None,
+            };
+            match (segment.ident.to_string().as_str(), argument) {
+                ("EntityID", None) if segment.arguments.is_empty() => Some(quote!{ MapEntities::map_entities(#value, map); }),
+                (_, None) if v.path.segments.len() == 1 && params.contains(&segment.ident) => Some(quote!{ SerializeComponent::map_entities(#value, map); }),
+                ("Option", Some(inner)) => map_type(inner, quote!{ #binding }, params, depth + 1)?.map(|inner| quote!{
+                    if let Some(#binding) = #value { #inner }
+                }),
+                ("Vec", Some(inner)) => map_type(inner, quote!{ #binding }, params, depth + 1)?.map(|inner| quote!{
+                    for #binding in (#value).iter_mut() { #inner }
+                }),
+                ("Box", Some(inner)) => map_type(inner, quote!{ &mut **(#value) }, params, depth + 1)?,
+                _ => None,
+            }
+        },
+        _ => None,
+    };
 
-    for pair in regex.captures_iter(&input) {
-        let name = &pair[1];
-        let id = pair[2].parse::<u64>().expect("Bad id file.");
-        map.insert(name.to_owned(), id);
+    if result.is_none() && names_entity_id(ty.to_token_stream()) {
+        return Err(Error::new_spanned(ty, "Can't remap the EntityIDs held by this type, only EntityID, Option, Vec, Box, arrays and tuples are supported. Implement SerializeComponent by hand instead"));
     }
+    return Ok(result);
+}
 
-    return map;
-}
\ No newline at end of file
+fn names_entity_id(tokens: TokenStream2) -> bool {
+    tokens.into_iter().any(|v| match v {
+        TokenTree::Ident(v) => v == "EntityID",
+        TokenTree::Group(v) => names_entity_id(v.stream()),
+        _                   => false,
+    })
+}
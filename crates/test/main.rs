@@ -4,19 +4,19 @@
 use butterscotch::{container::ChunkSize, ecs::{Component, ComponentID, ComponentRequestTupleDefinition, ECS, EntityID, OptRefComponents, ReqRefComponents}};
 
 #[derive(Debug, Component)]
-#[component_ns = "Butterscotch"]
+#[component(namespace = "Butterscotch")]
 struct Component1 {}
 
 #[derive(Debug, Component)]
-#[component_ns = "Butterscotch"]
+#[component(namespace = "Butterscotch")]
 struct Component2 {}
 
 #[derive(Debug, Component)]
-#[component_ns = "Butterscotch"]
+#[component(namespace = "Butterscotch")]
 struct Component3 {}
 
 #[derive(Debug, Component)]
-#[component_ns = "Butterscotch"]
+#[component(namespace = "Butterscotch")]
 struct Component4 {}
 
 fn main() {
@@ -25,7 +25,16 @@ fn main() {
     ecs.register_component::<Component2>(ChunkSize::Elements(4096));
     ecs.register_component::<Component3>(ChunkSize::Elements(4096));
     ecs.register_component::<Component4>(ChunkSize::Elements(4096));
-    if let Some(v) = call::<((Component1, Component2), ())>(&ecs, EntityID::new()) {
+
+    let eid = ecs.spawn();
+    ecs.attach(eid, Component1{});
+    ecs.attach(eid, Component2{});
+    if let Some(v) = call::<((Component1, Component2), ())>(&ecs, eid) {
+        println!("{:?}", v);
+    }
+
+    ecs.register_query::<((Component1,), (Component2, Component3))>();
+    for v in ecs.query::<((Component1,), (Component2, Component3))>() {
         println!("{:?}", v);
     }
 }
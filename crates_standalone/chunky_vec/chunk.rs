@@ -62,6 +62,10 @@ impl<T> Chunk<T> {
     pub fn capacity(&self) -> usize {
         self.0.capacity()
     }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.0.iter_mut()
+    }
 }
 
 impl<T> Index<usize> for Chunk<T> {
@@ -98,7 +98,7 @@ impl<T> ChunkyVec<T> {
     pub fn swap_remove(&mut self, index: usize) -> T {
         assert!(self.chunks_used > 0, "Attempt to index out of bounds");
         let tmp = self.pop().unwrap();
-        if index+1 == self.len() { return tmp; }
+        if index == self.len() { return tmp; }
         return std::mem::replace(&mut self[index], tmp);
     }
 
@@ -147,7 +147,7 @@ impl<T: Clone> ChunkyVec<T> {
     pub fn extend(&mut self, len: usize, value: T) {
         let vec_len = self.len();
         self.reserve(len - vec_len);
-        for _ in len..vec_len {
+        for _ in vec_len..len {
             self.push(value.clone());
         }
     }
@@ -167,7 +167,7 @@ impl<T> ChunkyVec<T> {
     pub fn extend_with<F>(&mut self, len: usize, mut f: F) where F: FnMut() -> T {
         let vec_len = self.len();
         self.reserve(len - vec_len);
-        for _ in len..vec_len {
+        for _ in vec_len..len {
             self.push(f());
         }
     }
@@ -175,9 +175,9 @@ impl<T> ChunkyVec<T> {
     pub fn reserve(&mut self, count: usize) {
         let remaining = self.capacity() - self.len();
         if remaining >= count { return; }
-        // TODO is this correct?
         let chunk_size = self.chunk_size;
-        self.chunks.resize_with(1+(count - remaining)/self.chunk_size, || Chunk::new(chunk_size));
+        let additional = (count - remaining + chunk_size - 1)/chunk_size;
+        self.chunks.resize_with(self.chunks.len() + additional, || Chunk::new(chunk_size));
     }
     
     pub fn reserve_exact(&mut self, count: usize) {
@@ -231,6 +231,15 @@ impl<T> ChunkyVec<T> {
             vec: &self
         }
     }
+
+    pub fn iter_mut<'a>(&'a mut self) -> ChunkyVecIterMut<'a, T> {
+        let len = self.len();
+        ChunkyVecIterMut{
+            remaining: len,
+            chunks: self.chunks[..self.chunks_used].iter_mut(),
+            current: Default::default(),
+        }
+    }
 }
 
 impl<T> Index<usize> for ChunkyVec<T> {
@@ -274,6 +283,30 @@ impl<'a, T> Iterator for ChunkyVecIter<'a, T> {
     }
 }
 
+pub struct ChunkyVecIterMut<'a, T> {
+    chunks: std::slice::IterMut<'a, Chunk<T>>,
+    current: std::slice::IterMut<'a, T>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for ChunkyVecIterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(v) = self.current.next() {
+                self.remaining -= 1;
+                return Some(v);
+            }
+            self.current = self.chunks.next()?.iter_mut();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
 
 
 #[cfg(test)]
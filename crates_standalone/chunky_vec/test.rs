@@ -106,3 +106,62 @@ pub fn pop_values(v: &mut ChunkyVec<usize>, count: usize) -> Result<(), String>
 
     Ok(())
 }
+
+#[test]
+fn test_swap_remove() -> Result<(), String> {
+    let mut v = ChunkyVec::<usize>::new(ChunkSize::Elements(4));
+    push_values(&mut v, 10)?;
+
+    // // Remove Last // //
+    assert_eq!(v.swap_remove(9), 9);
+    assert_eq!(v.len(),          9);
+    v.check_integrity()?;
+
+    // // Remove Middle // //
+    assert_eq!(v.swap_remove(2), 2);
+    assert_eq!(v[2],             8);
+    assert_eq!(v.len(),          8);
+    v.check_integrity()?;
+
+    // // Remove Only // //
+    v.clear();
+    v.push(7);
+    assert_eq!(v.swap_remove(0), 7);
+    assert!(v.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_resize() -> Result<(), String> {
+    let mut v = ChunkyVec::<usize>::new(ChunkSize::Elements(4));
+
+    // // Grow // //
+    v.resize(10, 3);
+    assert_eq!(v.len(), 10);
+    assert_eq!(v.capacity(), 12);
+    assert!(v.iter().all(|v| *v == 3));
+    v.check_integrity()?;
+
+    // // Reserve keeps existing chunks // //
+    v.reserve(5);
+    assert_eq!(v.len(), 10);
+    assert_eq!(v.capacity(), 16);
+    assert_eq!(v[9], 3);
+    v.check_integrity()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_iter_mut() -> Result<(), String> {
+    let mut v = ChunkyVec::<usize>::new(ChunkSize::Elements(4));
+    push_values(&mut v, 10)?;
+
+    assert_eq!(v.iter_mut().size_hint(), (10, Some(10)));
+    for value in v.iter_mut() { *value *= 2; }
+    assert!(v.iter().enumerate().all(|(i, v)| *v == i*2));
+    assert_eq!(v.iter_mut().count(), 10);
+
+    Ok(())
+}